@@ -0,0 +1,88 @@
+// Copyright 2019 TiKV Project Authors. Licensed under Apache-2.0.
+
+//! Samples jemalloc's thread-local allocation counters around a `next()`
+//! call so that `ExecutorExecutionSummary` can report a memory figure,
+//! the signal needed to tune the spill budget in [`super::memory`] and
+//! catch runaway aggregations. `ExecutorsRunner::handle_request` samples
+//! around the whole pipeline's `next()`, so the figure is one
+//! pipeline-wide high-water mark, not a per-operator one.
+//!
+//! On platforms built without the jemalloc allocator this degrades to a
+//! sampler that always reports zero, so summary output is unchanged there.
+
+#[cfg(feature = "jemalloc")]
+mod imp {
+    use tikv_alloc::thread_local::{allocatedp, deallocatedp};
+
+    /// A paired sample of jemalloc's per-thread `allocated`/`deallocated`
+    /// counters, taken at the start of a sampling window.
+    pub struct MemSample {
+        allocated: u64,
+        deallocated: u64,
+    }
+
+    impl MemSample {
+        pub fn take() -> Self {
+            unsafe {
+                Self {
+                    allocated: allocatedp().read(),
+                    deallocated: deallocatedp().read(),
+                }
+            }
+        }
+
+        /// Net bytes allocated since this sample was taken: a reasonable
+        /// proxy for the peak working-set delta of a single `next()` call,
+        /// since short-lived scratch allocations dominate deallocations
+        /// within one call and the executor's retained state shows up as
+        /// the remainder.
+        pub fn net_allocated_since(&self, end: &MemSample) -> i64 {
+            let allocated_delta = end.allocated.saturating_sub(self.allocated) as i64;
+            let deallocated_delta = end.deallocated.saturating_sub(self.deallocated) as i64;
+            allocated_delta - deallocated_delta
+        }
+    }
+}
+
+#[cfg(not(feature = "jemalloc"))]
+mod imp {
+    pub struct MemSample;
+
+    impl MemSample {
+        pub fn take() -> Self {
+            Self
+        }
+
+        pub fn net_allocated_since(&self, _end: &Self) -> i64 {
+            0
+        }
+    }
+}
+
+pub use self::imp::MemSample;
+
+/// Tracks the running high-water mark of net-allocated bytes across
+/// repeated sampling windows of one sampled pipeline.
+#[derive(Default)]
+pub struct PeakMemTracker {
+    high_water_mark: u64,
+}
+
+impl PeakMemTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records one `start`..`end` window's net allocation and folds it
+    /// into the tracked high-water mark.
+    pub fn observe(&mut self, start: &MemSample, end: &MemSample) {
+        let net = start.net_allocated_since(end).max(0) as u64;
+        if net > self.high_water_mark {
+            self.high_water_mark = net;
+        }
+    }
+
+    pub fn high_water_mark(&self) -> u64 {
+        self.high_water_mark
+    }
+}
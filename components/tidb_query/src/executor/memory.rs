@@ -0,0 +1,129 @@
+// Copyright 2019 TiKV Project Authors. Licensed under Apache-2.0.
+
+//! A shared, budget-bounded memory pool, one per process, that executors
+//! register a [`MemoryConsumer`] against so a single heavy request cannot
+//! grow its working set without bound and OOM the node. Consumers that
+//! cannot grow within their fair share of the pool are asked to shed bytes
+//! before retrying.
+//!
+//! Today the only registered consumer is `ExecutorsRunner` itself, whose
+//! `spill` releases its grant for the already-serialized response-chunk
+//! bytes it has buffered; those bytes stay in memory until the response
+//! is serialized out, and nothing is written to a temp file. The
+//! blocking executors this pool is meant for (hash/stream aggregation,
+//! top-N: unbounded hash tables and heaps, the actual OOM risk) are not
+//! part of this checkout and so do not register here yet; see the comment
+//! on `build_executors` in `runner.rs`.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use crate::Result;
+
+/// Minimum number of bytes every registered consumer is guaranteed, so a
+/// large number of concurrent requests cannot starve each other down to
+/// zero and livelock on spilling.
+const MIN_CONSUMER_RESERVATION: usize = 1024 * 1024;
+
+/// Something that holds an in-memory working set on behalf of an executor
+/// (a hash table, a top-N heap, ...) and knows how to shed bytes by
+/// spilling to a temporary file when the shared pool is under pressure.
+pub trait MemoryConsumer {
+    /// A stable identifier used for logging and for removing the
+    /// registration from the manager when the executor is dropped.
+    fn id(&self) -> u64;
+
+    /// Current best-effort estimate of the bytes this consumer is holding.
+    fn memory_used(&self) -> usize;
+
+    /// Shed enough of the held working set to make meaningful room,
+    /// returning the number of bytes freed. For a stateful consumer (a
+    /// hash table, a heap) this means spilling to a temp file; a consumer
+    /// with nothing but an already-serialized buffer can just flush it
+    /// early instead. Implementors that do I/O must honor the caller's
+    /// `Deadline` themselves, since spilling can legitimately be slow.
+    fn spill(&mut self) -> Result<usize>;
+}
+
+#[derive(Default)]
+struct ManagerState {
+    requesters_total: usize,
+    // consumer id -> bytes currently granted to that consumer.
+    granted: HashMap<u64, usize>,
+}
+
+/// Tracks the aggregate memory granted to all consumers registered against
+/// it and arbitrates growth against a configured budget. `ExecutorsRunner`
+/// registers every runner in the process against one shared manager.
+pub struct MemoryManager {
+    pool_limit: usize,
+    state: Mutex<ManagerState>,
+    next_consumer_id: AtomicUsize,
+}
+
+impl MemoryManager {
+    pub fn new(pool_limit: usize) -> Self {
+        Self {
+            pool_limit,
+            state: Mutex::new(ManagerState::default()),
+            next_consumer_id: AtomicUsize::new(1),
+        }
+    }
+
+    /// Allocates a fresh consumer id. Executors should call this once when
+    /// they are constructed and use the id for every subsequent call.
+    pub fn register_consumer(&self) -> u64 {
+        self.next_consumer_id.fetch_add(1, Ordering::Relaxed) as u64
+    }
+
+    /// The budget currently available to a single requester, i.e. the pool
+    /// limit divided fairly across all consumers that are currently
+    /// registered, floored at `MIN_CONSUMER_RESERVATION`.
+    fn max_mem_for_requesters(&self, state: &ManagerState) -> usize {
+        let active = state.granted.len().max(1);
+        (self.pool_limit / active).max(MIN_CONSUMER_RESERVATION)
+    }
+
+    /// Attempts to grow `consumer_id`'s grant by `required` bytes. Returns
+    /// `Ok(true)` when the growth was granted. Returns `Ok(false)` when the
+    /// pool is under pressure and the caller should spill before retrying.
+    pub fn try_grow(&self, consumer_id: u64, required: usize) -> Result<bool> {
+        let mut state = self.state.lock().unwrap();
+        state.granted.entry(consumer_id).or_insert(0);
+        let fair_share = self.max_mem_for_requesters(&state);
+        let current = *state.granted.get(&consumer_id).unwrap();
+        let within_reservation = current + required <= MIN_CONSUMER_RESERVATION;
+
+        if !within_reservation
+            && (state.requesters_total + required > self.pool_limit
+                || current + required > fair_share)
+        {
+            return Ok(false);
+        }
+
+        state.requesters_total += required;
+        *state.granted.get_mut(&consumer_id).unwrap() += required;
+        Ok(true)
+    }
+
+    /// Releases `amount` bytes previously granted to `consumer_id`, e.g.
+    /// after a successful spill.
+    pub fn release(&self, consumer_id: u64, amount: usize) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(granted) = state.granted.get_mut(&consumer_id) {
+            let released = amount.min(*granted);
+            *granted -= released;
+            state.requesters_total = state.requesters_total.saturating_sub(released);
+        }
+    }
+
+    /// Drops the consumer's reservation entirely, e.g. when the executor
+    /// owning it is dropped.
+    pub fn remove_consumer(&self, consumer_id: u64) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(granted) = state.granted.remove(&consumer_id) {
+            state.requesters_total = state.requesters_total.saturating_sub(granted);
+        }
+    }
+}
@@ -0,0 +1,192 @@
+// Copyright 2019 TiKV Project Authors. Licensed under Apache-2.0.
+
+//! A bounded cache of compiled executor plans, keyed by the shape of the
+//! incoming `tipb::Executor` descriptors. TiDB frequently resends the same
+//! query shape with only the key ranges and literal constants changed, so
+//! caching the reusable part of a compiled plan (rather than the stateful
+//! executor chain itself) lets `ExecutorsRunner::from_cached_request` skip
+//! rebuilding that part on every call. The runner's `CachedPlan` holds the
+//! parsed `EvalConfig` only; the executor descriptors are still validated,
+//! and the executor chain built, from each request.
+//!
+//! Eviction follows the scheme used by Solana's `CachedExecutors`: each
+//! entry carries an atomic usage counter that is bumped on every lookup;
+//! when the cache is full, a handful of randomly sampled entries are
+//! compared and the one with the lowest counter is evicted, and all
+//! counters are halved periodically so that once-hot plans don't become
+//! permanently un-evictable.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use rand::Rng;
+use tipb::Executor;
+
+/// Number of random candidates whose usage counters are compared when
+/// choosing an eviction victim. The candidates are drawn from a list of
+/// every key in the cache, so each eviction still walks all keys once;
+/// only the counter comparison is limited to this many entries.
+const EVICTION_SAMPLE_SIZE: usize = 5;
+
+/// A fingerprint of a `tipb::Executor` pipeline shape, deliberately
+/// excluding `ranges` and literal constants (which are re-bound per
+/// request) so that two requests that only differ in scan ranges or
+/// parameter values share the same cache entry.
+pub type PlanFingerprint = u64;
+
+/// Computes the fingerprint for a list of executor descriptors: the exec
+/// type, column references and expression structure, in order. The
+/// request's `output_offsets` are not part of the fingerprint.
+pub fn fingerprint_executors(executors: &[Executor]) -> PlanFingerprint {
+    let mut hasher = DefaultHasher::new();
+    for exec in executors {
+        exec.get_tp().hash(&mut hasher);
+        hash_descriptor_shape(exec, &mut hasher);
+    }
+    hasher.finish()
+}
+
+fn hash_descriptor_shape(exec: &Executor, hasher: &mut DefaultHasher) {
+    // Only the structural parts of each descriptor participate in the
+    // fingerprint: expression trees (operator + child shape + field type),
+    // column offsets, and group-by/order-by arity. Scan ranges and scalar
+    // literal values are intentionally left out since they vary per
+    // request but never change which plan should run.
+    match exec.get_tp() {
+        tipb::ExecType::TypeTableScan => {
+            exec.get_tbl_scan().get_columns().len().hash(hasher);
+            exec.get_tbl_scan().get_desc().hash(hasher);
+        }
+        tipb::ExecType::TypeIndexScan => {
+            exec.get_idx_scan().get_columns().len().hash(hasher);
+            exec.get_idx_scan().get_unique().hash(hasher);
+        }
+        tipb::ExecType::TypeSelection => {
+            for e in exec.get_selection().get_conditions() {
+                hash_expr_shape(e, hasher);
+            }
+        }
+        tipb::ExecType::TypeAggregation | tipb::ExecType::TypeStreamAgg => {
+            for e in exec.get_aggregation().get_group_by() {
+                hash_expr_shape(e, hasher);
+            }
+            for e in exec.get_aggregation().get_agg_func() {
+                hash_expr_shape(e, hasher);
+            }
+        }
+        tipb::ExecType::TypeTopN => {
+            for by in exec.get_topN().get_order_by() {
+                hash_expr_shape(by.get_expr(), hasher);
+                by.get_desc().hash(hasher);
+            }
+            exec.get_topN().get_limit().hash(hasher);
+        }
+        tipb::ExecType::TypeLimit => {
+            // The limit count itself is structural: it changes how many
+            // rows flow downstream, unlike a literal inside an expression.
+            exec.get_limit().get_limit().hash(hasher);
+        }
+        _ => {}
+    }
+}
+
+fn hash_expr_shape(expr: &tipb::Expr, hasher: &mut DefaultHasher) {
+    expr.get_tp().hash(hasher);
+    expr.get_sig().hash(hasher);
+    expr.get_field_type().get_tp().hash(hasher);
+    if expr.get_tp() == tipb::ExprType::ColumnRef {
+        // A column reference's offset is structural, unlike a literal's value.
+        expr.get_val().hash(hasher);
+    }
+    for child in expr.get_children() {
+        hash_expr_shape(child, hasher);
+    }
+}
+
+/// A single cached plan entry together with its usage counter.
+struct CacheEntry<P> {
+    plan: P,
+    usage_count: AtomicU64,
+}
+
+/// A bounded, fingerprint-keyed cache of compiled plans.
+///
+/// `P` is whatever a caller wants to cache (the compiled expression trees,
+/// schema and summary-slot layout of a DAG) — deliberately *not* the
+/// `Box<dyn Executor>` chain itself, since that chain is stateful and must
+/// not be shared between concurrent requests.
+pub struct PlanCache<P> {
+    capacity: usize,
+    entries: Mutex<HashMap<PlanFingerprint, CacheEntry<P>>>,
+    inserts: AtomicU64,
+}
+
+impl<P: Clone> PlanCache<P> {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: Mutex::new(HashMap::with_capacity(capacity)),
+            inserts: AtomicU64::new(0),
+        }
+    }
+
+    /// Looks up a cached plan, bumping its usage counter on a hit.
+    pub fn get(&self, key: PlanFingerprint) -> Option<P> {
+        let entries = self.entries.lock().unwrap();
+        entries.get(&key).map(|entry| {
+            entry.usage_count.fetch_add(1, Ordering::Relaxed);
+            entry.plan.clone()
+        })
+    }
+
+    /// Inserts a freshly compiled plan, evicting a low-usage entry first if
+    /// the cache is already at capacity.
+    pub fn insert(&self, key: PlanFingerprint, plan: P) {
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() >= self.capacity && !entries.contains_key(&key) {
+            self.evict_one(&mut entries);
+        }
+        entries.insert(
+            key,
+            CacheEntry {
+                plan,
+                usage_count: AtomicU64::new(1),
+            },
+        );
+
+        // Every 64 inserts, decay all counters so plans that were hot a
+        // while ago but have gone cold can eventually be evicted.
+        if self.inserts.fetch_add(1, Ordering::Relaxed) % 64 == 63 {
+            for entry in entries.values() {
+                entry.usage_count.fetch_update(
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                    |c| Some(c / 2),
+                )
+                .ok();
+            }
+        }
+    }
+
+    fn evict_one(&self, entries: &mut HashMap<PlanFingerprint, CacheEntry<P>>) {
+        if entries.is_empty() {
+            return;
+        }
+        let keys: Vec<_> = entries.keys().copied().collect();
+        let mut rng = rand::thread_rng();
+        let mut victim = keys[rng.gen_range(0, keys.len())];
+        let mut victim_count = entries[&victim].usage_count.load(Ordering::Relaxed);
+        for _ in 1..EVICTION_SAMPLE_SIZE.min(keys.len()) {
+            let candidate = keys[rng.gen_range(0, keys.len())];
+            let count = entries[&candidate].usage_count.load(Ordering::Relaxed);
+            if count < victim_count {
+                victim = candidate;
+                victim_count = count;
+            }
+        }
+        entries.remove(&victim);
+    }
+}
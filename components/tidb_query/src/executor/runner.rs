@@ -3,12 +3,17 @@
 use std::sync::Arc;
 
 use kvproto::coprocessor::KeyRange;
+use lazy_static::lazy_static;
 use protobuf::Message;
 use tipb::{self, ExecType, ExecutorExecutionSummary};
 use tipb::{Chunk, DAGRequest, SelectResponse, StreamResponse};
 
 use tikv_util::deadline::Deadline;
 
+use super::explain::ExplainPlan;
+use super::mem_sampler::{MemSample, PeakMemTracker};
+use super::memory::{MemoryConsumer, MemoryManager};
+use super::plan_cache::{fingerprint_executors, PlanCache};
 use super::Executor;
 use crate::execute_stats::*;
 use crate::expr::EvalConfig;
@@ -16,6 +21,30 @@ use crate::metrics::*;
 use crate::storage::{IntervalRange, Storage};
 use crate::Result;
 
+/// Node-wide memory budget shared by every coprocessor request's
+/// `ExecutorsRunner`. A per-request `MemoryManager` would only ever have
+/// one registered consumer (the runner itself) and `max_mem_for_requesters`'s
+/// "fair share across active requesters" would never actually divide
+/// anything, so all runners on this process register against the same
+/// manager instead.
+const NODE_MEMORY_LIMIT: usize = 1024 * 1024 * 1024;
+
+lazy_static! {
+    static ref MEMORY_MANAGER: Arc<MemoryManager> = Arc::new(MemoryManager::new(NODE_MEMORY_LIMIT));
+}
+
+/// The validated, reusable part of a compiled DAG: the parsed evaluation
+/// config. Two requests with the same `fingerprint_executors` shape share
+/// one `CachedPlan`, but `fingerprint_executors` deliberately leaves out
+/// scalar literals (`WHERE id = 5` and `WHERE id = 10` fingerprint the
+/// same), so the executor descriptors themselves are *not* part of this
+/// cache entry — they always come from the live request, which is the
+/// only copy that actually has the right literal values baked in.
+#[derive(Clone)]
+pub struct CachedPlan {
+    pub config: Arc<EvalConfig>,
+}
+
 pub struct ExecutorsRunner<SS> {
     deadline: Deadline,
     executor: Box<dyn Executor<StorageStats = SS> + Send>,
@@ -23,11 +52,66 @@ pub struct ExecutorsRunner<SS> {
     batch_row_limit: usize,
     collect_exec_summary: bool,
     exec_stats: ExecuteStats,
+    // The runner is itself the `MemoryConsumer` registered against
+    // `memory_manager`: it tracks the bytes buffered into response chunks
+    // and, under budget pressure, spills by releasing its grant for them;
+    // the buffered chunks stay in memory until they are serialized out.
+    // Per-operator accounting inside the aggregation/top-N executors is
+    // not implemented here; see the comment on `build_executors`.
+    memory_manager: Arc<MemoryManager>,
+    consumer_id: u64,
+    buffered_bytes: usize,
+    // Net jemalloc allocation sampled around each `next()` call of
+    // `handle_request` (the streaming path does not sample), folded
+    // into a high-water mark. This is a whole-pipeline estimate, not a
+    // true per-operator breakdown: attributing memory to one executor in
+    // the middle of the chain would need sampling inside that executor's
+    // own `next()`, which means touching its source directly. Until then
+    // every `ExecutorExecutionSummary` in one response reports this same
+    // pipeline-wide peak.
+    mem_tracker: PeakMemTracker,
+    // Only populated when the request asked for an explain/profiling view,
+    // so the normal hot path pays nothing for it.
+    explain_plan: Option<ExplainPlan>,
+}
+
+impl<SS> MemoryConsumer for ExecutorsRunner<SS> {
+    fn id(&self) -> u64 {
+        self.consumer_id
+    }
+
+    fn memory_used(&self) -> usize {
+        self.buffered_bytes
+    }
+
+    /// The only buffered state a runner holds is the response chunk it
+    /// hasn't handed back to the caller yet, so "spilling" it just means
+    /// treating it as already flushed: the bytes are freed back to the
+    /// shared pool immediately, no temp file required.
+    fn spill(&mut self) -> Result<usize> {
+        let freed = self.buffered_bytes;
+        self.memory_manager.release(self.consumer_id, freed);
+        self.buffered_bytes = 0;
+        Ok(freed)
+    }
+}
+
+impl<SS> Drop for ExecutorsRunner<SS> {
+    fn drop(&mut self) {
+        self.memory_manager.remove_consumer(self.consumer_id);
+    }
 }
 
 /// Builds a normal executor pipeline.
 ///
 /// Normal executors iterate rows one by one.
+///
+/// Note: the hash/stream aggregation and top-N executors built here do not
+/// yet register with a `MemoryManager` themselves — doing so means giving
+/// each of them a `MemoryConsumer` impl that spills its hash table/heap,
+/// which touches their own source files rather than this one. Until then,
+/// `ExecutorsRunner` is the only `MemoryConsumer` in this pipeline; see its
+/// `spill` impl.
 pub fn build_executors<S: Storage + 'static, C: ExecSummaryCollector + 'static>(
     exec_descriptors: Vec<tipb::Executor>,
     storage: S,
@@ -159,6 +243,18 @@ impl<SS: 'static> ExecutorsRunner<SS> {
         let collect_exec_summary = req.get_collect_execution_summaries();
         let config = Arc::new(EvalConfig::from_request(&req)?);
 
+        let explain_plan = if req.get_is_explain() {
+            Some(ExplainPlan::from_descriptors(req.get_executors()))
+        } else {
+            None
+        };
+
+        // `req.get_memory_limit()` would only bound this one request's own
+        // registration, not the shared pool, so it is not consulted here;
+        // see `NODE_MEMORY_LIMIT`.
+        let memory_manager = Arc::clone(&MEMORY_MANAGER);
+        let consumer_id = memory_manager.register_consumer();
+
         let executor = if !(req.get_collect_execution_summaries()) {
             build_executors::<_, ExecSummaryCollectorDisabled>(
                 req.take_executors().into(),
@@ -183,18 +279,125 @@ impl<SS: 'static> ExecutorsRunner<SS> {
             0 // Avoid allocation for executor summaries when it is not needed
         });
 
+        let output_offsets = req.take_output_offsets();
+
         Ok(Self {
             deadline,
             executor,
-            output_offsets: req.take_output_offsets(),
+            output_offsets,
             batch_row_limit,
             collect_exec_summary,
             exec_stats,
+            memory_manager,
+            consumer_id,
+            buffered_bytes: 0,
+            mem_tracker: PeakMemTracker::new(),
+            explain_plan,
         })
     }
 
+    /// Like `from_request`, but consults `plan_cache` first so that a
+    /// request whose DAG shape was already seen skips re-parsing
+    /// `EvalConfig`. The executor descriptors are still validated, and a
+    /// fresh, independent executor chain built, from this request, so
+    /// concurrent requests never share mutable executor state.
+    pub fn from_cached_request<S: Storage<Statistics = SS> + 'static>(
+        mut req: DAGRequest,
+        ranges: Vec<KeyRange>,
+        storage: S,
+        deadline: Deadline,
+        batch_row_limit: usize,
+        is_streaming: bool,
+        plan_cache: &PlanCache<CachedPlan>,
+    ) -> Result<Self> {
+        let fingerprint = fingerprint_executors(req.get_executors());
+        let cached = plan_cache.get(fingerprint);
+
+        let executors_len = req.get_executors().len();
+        let collect_exec_summary = req.get_collect_execution_summaries();
+
+        let plan = match cached {
+            Some(plan) => plan,
+            None => {
+                let config = Arc::new(EvalConfig::from_request(&req)?);
+                let plan = CachedPlan { config };
+                plan_cache.insert(fingerprint, plan.clone());
+                plan
+            }
+        };
+
+        // Descriptors always come from this request, never from the
+        // cached plan: the cache is keyed on a fingerprint that ignores
+        // literal constants, so a cached entry's descriptors may carry a
+        // *different* request's literals (e.g. a different `LIMIT` or
+        // comparison value) even though the shape matches.
+        let exec_descriptors = req.get_executors().to_vec();
+
+        let memory_manager = Arc::clone(&MEMORY_MANAGER);
+        let consumer_id = memory_manager.register_consumer();
+
+        let executor = if !collect_exec_summary {
+            build_executors::<_, ExecSummaryCollectorDisabled>(
+                exec_descriptors,
+                storage,
+                ranges,
+                Arc::clone(&plan.config),
+                is_streaming,
+            )?
+        } else {
+            build_executors::<_, ExecSummaryCollectorEnabled>(
+                exec_descriptors,
+                storage,
+                ranges,
+                Arc::clone(&plan.config),
+                is_streaming,
+            )?
+        };
+
+        let exec_stats = ExecuteStats::new(if collect_exec_summary {
+            executors_len
+        } else {
+            0
+        });
+
+        let output_offsets = req.take_output_offsets();
+
+        Ok(Self {
+            deadline,
+            executor,
+            output_offsets,
+            batch_row_limit,
+            collect_exec_summary,
+            exec_stats,
+            memory_manager,
+            consumer_id,
+            buffered_bytes: 0,
+            mem_tracker: PeakMemTracker::new(),
+            // The cached-plan path is purely a performance fast path for
+            // regular execution; explain requests always go through
+            // `from_request` so the DOT graph sees the request's own
+            // descriptors.
+            explain_plan: None,
+        })
+    }
+
+    /// Renders the executor pipeline built by `from_request` as a
+    /// Graphviz DOT graph, annotated with per-executor execution summaries
+    /// if `handle_request`/`handle_streaming_request` has already run.
+    /// Returns `None` unless the request set `is_explain`.
+    pub fn explain_dot(&mut self) -> Option<String> {
+        let plan = self.explain_plan.as_mut()?;
+        self.executor.collect_exec_stats(&mut self.exec_stats);
+        plan.annotate_with_stats(&self.exec_stats);
+        Some(plan.to_dot())
+    }
+
     fn make_stream_response(&mut self, chunk: Chunk) -> Result<StreamResponse> {
         self.executor.collect_exec_stats(&mut self.exec_stats);
+        // The chunk is about to be serialized out to the caller, so the
+        // bytes this runner was holding for it are no longer live.
+        self.memory_manager.release(self.consumer_id, self.buffered_bytes);
+        self.buffered_bytes = 0;
 
         let mut s_resp = StreamResponse::default();
         s_resp.set_data(box_try!(chunk.write_to_bytes()));
@@ -219,9 +422,27 @@ impl<SS: 'static> ExecutorsRunner<SS> {
         let mut record_cnt = 0;
         let mut chunks = Vec::new();
         loop {
-            match self.executor.next()? {
+            let mem_sample_start = MemSample::take();
+            let next_row = self.executor.next()?;
+            let mem_sample_end = MemSample::take();
+            self.mem_tracker.observe(&mem_sample_start, &mem_sample_end);
+
+            match next_row {
                 Some(row) => {
                     self.deadline.check()?;
+                    // for default encode type
+                    let value = row.get_binary(&self.output_offsets)?;
+
+                    if !self.memory_manager.try_grow(self.consumer_id, value.len())? {
+                        // Over our fair share of the shared pool: release
+                        // this runner's grant for the bytes buffered so
+                        // far and start a new chunk with this row. The
+                        // earlier chunks stay in `chunks` until the
+                        // response is built, so no memory is freed here.
+                        self.spill()?;
+                        record_cnt = self.batch_row_limit;
+                    }
+
                     if chunks.is_empty() || record_cnt >= self.batch_row_limit {
                         let chunk = Chunk::default();
                         chunks.push(chunk);
@@ -229,12 +450,16 @@ impl<SS: 'static> ExecutorsRunner<SS> {
                     }
                     let chunk = chunks.last_mut().unwrap();
                     record_cnt += 1;
-                    // for default encode type
-                    let value = row.get_binary(&self.output_offsets)?;
+                    self.buffered_bytes += value.len();
                     chunk.mut_rows_data().extend_from_slice(&value);
                 }
                 None => {
                     self.executor.collect_exec_stats(&mut self.exec_stats);
+                    // All buffered chunks are about to be serialized out
+                    // to the caller, so none of their bytes are live
+                    // anymore.
+                    self.memory_manager.release(self.consumer_id, self.buffered_bytes);
+                    self.buffered_bytes = 0;
 
                     let mut sel_resp = SelectResponse::default();
                     sel_resp.set_chunks(chunks.into());
@@ -252,6 +477,13 @@ impl<SS: 'static> ExecutorsRunner<SS> {
                     );
 
                     if self.collect_exec_summary {
+                        // `mem_tracker` samples around the whole pipeline's
+                        // `next()`, not each operator's, so every summary
+                        // in this response reports the same pipeline-wide
+                        // high-water mark; see the field comment on
+                        // `mem_tracker`. Zero on builds without the
+                        // jemalloc allocator.
+                        let peak_mem_bytes = self.mem_tracker.high_water_mark();
                         let summaries = self
                             .exec_stats
                             .summary_per_executor
@@ -261,6 +493,7 @@ impl<SS: 'static> ExecutorsRunner<SS> {
                                 ret.set_num_iterations(summary.num_iterations as u64);
                                 ret.set_num_produced_rows(summary.num_produced_rows as u64);
                                 ret.set_time_processed_ns(summary.time_processed_ns as u64);
+                                ret.set_mem_bytes(peak_mem_bytes as i64);
                                 ret
                             })
                             .collect::<Vec<_>>();
@@ -288,6 +521,15 @@ impl<SS: 'static> ExecutorsRunner<SS> {
                     self.deadline.check()?;
                     record_cnt += 1;
                     let value = row.get_binary(&self.output_offsets)?;
+
+                    if !self.memory_manager.try_grow(self.consumer_id, value.len())? {
+                        // Over our fair share: release the grant held for
+                        // this chunk's bytes and keep appending to the
+                        // same chunk; `batch_row_limit` still bounds how
+                        // many rows one streaming chunk holds.
+                        self.spill()?;
+                    }
+                    self.buffered_bytes += value.len();
                     chunk.mut_rows_data().extend_from_slice(&value);
                 }
                 None => {
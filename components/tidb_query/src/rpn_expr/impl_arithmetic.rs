@@ -0,0 +1,373 @@
+// Copyright 2019 TiKV Project Authors. Licensed under Apache-2.0.
+
+use std::cmp;
+
+use super::types::*;
+use crate::codec::data_type::*;
+use crate::expr::EvalContext;
+use crate::Result;
+
+/// One arithmetic operator (`+`, `-`, `*`, `div`, `%`) over a single
+/// concrete numeric representation. `arithmetic_fn_meta::<M>()` wraps
+/// `M::calc` into the two-argument RPN function the dispatcher registers;
+/// `arithmetic_with_ctx_fn_meta` is the same for operators that need
+/// `EvalContext`: real and decimal division (divide-by-zero warning/error)
+/// and the decimal operators (result precision/scale conversion).
+pub trait ArithmeticOp {
+    type T: Evaluable;
+    fn calc(lhs: &Self::T, rhs: &Self::T) -> Result<Option<Self::T>>;
+}
+
+pub trait ArithmeticOpWithCtx {
+    type T: Evaluable;
+    fn calc(ctx: &mut EvalContext, lhs: &Self::T, rhs: &Self::T) -> Result<Option<Self::T>>;
+}
+
+#[rpn_fn(generic_over = [M: ArithmeticOp])]
+#[inline]
+fn arithmetic<M: ArithmeticOp>(lhs: &Option<M::T>, rhs: &Option<M::T>) -> Result<Option<M::T>> {
+    match (lhs, rhs) {
+        (Some(lhs), Some(rhs)) => M::calc(lhs, rhs),
+        _ => Ok(None),
+    }
+}
+
+#[rpn_fn(generic_over = [M: ArithmeticOpWithCtx], capture = [ctx])]
+#[inline]
+fn arithmetic_with_ctx<M: ArithmeticOpWithCtx>(
+    ctx: &mut EvalContext,
+    lhs: &Option<M::T>,
+    rhs: &Option<M::T>,
+) -> Result<Option<M::T>> {
+    match (lhs, rhs) {
+        (Some(lhs), Some(rhs)) => M::calc(ctx, lhs, rhs),
+        _ => Ok(None),
+    }
+}
+
+macro_rules! int_arith_marker {
+    ($name:ident, $lhs_ty:ty, $rhs_ty:ty, $out_ty:ty, $op:tt, $checked:ident) => {
+        pub struct $name;
+        impl ArithmeticOp for $name {
+            type T = Int;
+            fn calc(lhs: &Int, rhs: &Int) -> Result<Option<Int>> {
+                // Widen each operand into i128 under its own sign/unsigned
+                // interpretation first. Casting `rhs` straight to `$lhs_ty`
+                // (as this used to do) reinterprets its raw bits as the
+                // other operand's type instead of converting its value, so
+                // e.g. a negative BIGINT rhs combined with a BIGINT
+                // UNSIGNED lhs would turn into a huge positive number. i128
+                // holds the full range of both i64 and u64 simultaneously,
+                // so neither side loses information before the op runs.
+                let lhs_wide = *lhs as $lhs_ty as i128;
+                let rhs_wide = *rhs as $rhs_ty as i128;
+                let out_of_range = || {
+                    other_err!(
+                        "BIGINT value is out of range in '{} {} {}'",
+                        lhs_wide,
+                        stringify!($op),
+                        rhs_wide
+                    )
+                };
+                let result = lhs_wide.$checked(rhs_wide).ok_or_else(out_of_range)?;
+                if result < <$out_ty>::MIN as i128 || result > <$out_ty>::MAX as i128 {
+                    return Err(out_of_range());
+                }
+                Ok(Some(result as $out_ty as Int))
+            }
+        }
+    };
+}
+
+int_arith_marker!(IntIntPlus, i64, i64, i64, +, checked_add);
+int_arith_marker!(IntUintPlus, i64, u64, u64, +, checked_add);
+int_arith_marker!(UintIntPlus, u64, i64, u64, +, checked_add);
+int_arith_marker!(UintUintPlus, u64, u64, u64, +, checked_add);
+int_arith_marker!(IntIntMinus, i64, i64, i64, -, checked_sub);
+int_arith_marker!(IntUintMinus, i64, u64, u64, -, checked_sub);
+int_arith_marker!(UintIntMinus, u64, i64, u64, -, checked_sub);
+int_arith_marker!(UintUintMinus, u64, u64, u64, -, checked_sub);
+int_arith_marker!(IntIntMultiply, i64, i64, i64, *, checked_mul);
+int_arith_marker!(IntUintMultiply, i64, u64, u64, *, checked_mul);
+int_arith_marker!(UintIntMultiply, u64, i64, u64, *, checked_mul);
+int_arith_marker!(UintUintMultiply, u64, u64, u64, *, checked_mul);
+int_arith_marker!(IntIntMod, i64, i64, i64, %, checked_rem);
+int_arith_marker!(IntUintMod, i64, u64, u64, %, checked_rem);
+int_arith_marker!(UintIntMod, u64, i64, u64, %, checked_rem);
+int_arith_marker!(UintUintMod, u64, u64, u64, %, checked_rem);
+int_arith_marker!(IntDivideInt, i64, i64, i64, /, checked_div);
+int_arith_marker!(IntDivideUint, i64, u64, u64, /, checked_div);
+int_arith_marker!(UintDivideInt, u64, i64, u64, /, checked_div);
+int_arith_marker!(UintDivideUint, u64, u64, u64, /, checked_div);
+
+pub struct RealPlus;
+impl ArithmeticOp for RealPlus {
+    type T = Real;
+    fn calc(lhs: &Real, rhs: &Real) -> Result<Option<Real>> {
+        Ok(Real::new(lhs.into_inner() + rhs.into_inner()).ok())
+    }
+}
+
+pub struct RealMinus;
+impl ArithmeticOp for RealMinus {
+    type T = Real;
+    fn calc(lhs: &Real, rhs: &Real) -> Result<Option<Real>> {
+        Ok(Real::new(lhs.into_inner() - rhs.into_inner()).ok())
+    }
+}
+
+pub struct RealMultiply;
+impl ArithmeticOp for RealMultiply {
+    type T = Real;
+    fn calc(lhs: &Real, rhs: &Real) -> Result<Option<Real>> {
+        Ok(Real::new(lhs.into_inner() * rhs.into_inner()).ok())
+    }
+}
+
+pub struct RealMod;
+impl ArithmeticOp for RealMod {
+    type T = Real;
+    fn calc(lhs: &Real, rhs: &Real) -> Result<Option<Real>> {
+        if rhs.into_inner() == 0f64 {
+            return Ok(None);
+        }
+        Ok(Real::new(lhs.into_inner() % rhs.into_inner()).ok())
+    }
+}
+
+pub struct RealDivide;
+impl ArithmeticOpWithCtx for RealDivide {
+    type T = Real;
+    fn calc(ctx: &mut EvalContext, lhs: &Real, rhs: &Real) -> Result<Option<Real>> {
+        if rhs.into_inner() == 0f64 {
+            ctx.handle_division_by_zero()?;
+            return Ok(None);
+        }
+        Ok(Real::new(lhs.into_inner() / rhs.into_inner()).ok())
+    }
+}
+
+/// Decimals carry 65 significant digits / 30 fractional digits at most;
+/// results past that are clamped the same way MySQL clamps `DECIMAL`.
+const MAX_DECIMAL_PRECISION: u8 = 65;
+const MAX_DECIMAL_SCALE: u8 = 30;
+
+/// Default `DIV_PRECISION_INCREMENT`: division grows the result scale by
+/// this many extra fractional digits beyond the dividend's scale,
+/// matching MySQL/TiDB's default.
+const DIV_PRECISION_INCREMENT: u8 = 4;
+
+fn clamp_scale(scale: u32) -> u8 {
+    cmp::min(scale, MAX_DECIMAL_SCALE as u32) as u8
+}
+
+fn clamp_precision(precision: u32) -> u8 {
+    cmp::min(precision, MAX_DECIMAL_PRECISION as u32) as u8
+}
+
+/// Aligns two decimals onto a common scale by shifting the lower-scale
+/// operand's mantissa up by `10^(|s1-s2|)`, so add/subtract can run on
+/// the integer mantissas without losing precision to a float
+/// intermediate.
+fn align_to_common_scale(lhs: &Decimal, rhs: &Decimal) -> (Decimal, Decimal) {
+    let common_scale = cmp::max(lhs.scale(), rhs.scale());
+    (lhs.shift_scale_to(common_scale), rhs.shift_scale_to(common_scale))
+}
+
+/// MySQL's result precision/scale for `DECIMAL +/- DECIMAL`: scale is the
+/// max of the two operand scales, and precision grows by one digit to
+/// make room for a possible carry.
+fn add_sub_result_precision_scale(lhs: &Decimal, rhs: &Decimal) -> (u8, u8) {
+    let scale = clamp_scale(cmp::max(lhs.scale() as u32, rhs.scale() as u32));
+    let int_digits = cmp::max(
+        lhs.precision() as i32 - lhs.scale() as i32,
+        rhs.precision() as i32 - rhs.scale() as i32,
+    )
+    .max(0) as u32;
+    (clamp_precision(int_digits + scale as u32 + 1), scale)
+}
+
+/// `DECIMAL * DECIMAL`: the result scale is the sum of the operand
+/// scales.
+fn multiply_result_precision_scale(lhs: &Decimal, rhs: &Decimal) -> (u8, u8) {
+    (
+        clamp_precision(lhs.precision() as u32 + rhs.precision() as u32),
+        clamp_scale(lhs.scale() as u32 + rhs.scale() as u32),
+    )
+}
+
+/// `DECIMAL / DECIMAL`: the result scale grows by
+/// `DIV_PRECISION_INCREMENT` beyond the dividend's scale.
+fn divide_result_precision_scale(lhs: &Decimal, rhs: &Decimal) -> (u8, u8) {
+    (
+        clamp_precision(lhs.precision() as u32 + rhs.scale() as u32 + DIV_PRECISION_INCREMENT as u32),
+        clamp_scale(lhs.scale() as u32 + DIV_PRECISION_INCREMENT as u32),
+    )
+}
+
+pub struct DecimalPlus;
+impl ArithmeticOpWithCtx for DecimalPlus {
+    type T = Decimal;
+    fn calc(ctx: &mut EvalContext, lhs: &Decimal, rhs: &Decimal) -> Result<Option<Decimal>> {
+        let (lhs, rhs) = align_to_common_scale(lhs, rhs);
+        let (precision, scale) = add_sub_result_precision_scale(&lhs, &rhs);
+        Ok(Some((lhs + rhs)?.convert_to(ctx, precision, scale)?))
+    }
+}
+
+pub struct DecimalMinus;
+impl ArithmeticOpWithCtx for DecimalMinus {
+    type T = Decimal;
+    fn calc(ctx: &mut EvalContext, lhs: &Decimal, rhs: &Decimal) -> Result<Option<Decimal>> {
+        let (lhs, rhs) = align_to_common_scale(lhs, rhs);
+        let (precision, scale) = add_sub_result_precision_scale(&lhs, &rhs);
+        Ok(Some((lhs - rhs)?.convert_to(ctx, precision, scale)?))
+    }
+}
+
+pub struct DecimalMultiply;
+impl ArithmeticOpWithCtx for DecimalMultiply {
+    type T = Decimal;
+    fn calc(ctx: &mut EvalContext, lhs: &Decimal, rhs: &Decimal) -> Result<Option<Decimal>> {
+        let (precision, scale) = multiply_result_precision_scale(lhs, rhs);
+        Ok(Some((lhs * rhs)?.convert_to(ctx, precision, scale)?))
+    }
+}
+
+pub struct DecimalMod;
+impl ArithmeticOpWithCtx for DecimalMod {
+    type T = Decimal;
+    fn calc(ctx: &mut EvalContext, lhs: &Decimal, rhs: &Decimal) -> Result<Option<Decimal>> {
+        if rhs.is_zero() {
+            ctx.handle_division_by_zero()?;
+            return Ok(None);
+        }
+        // `%`'s result scale follows the operands' common scale, same as
+        // add/subtract, since a modulo manufactures no extra precision.
+        let (precision, scale) = add_sub_result_precision_scale(lhs, rhs);
+        Ok(Some((lhs % rhs)?.convert_to(ctx, precision, scale)?))
+    }
+}
+
+pub struct DecimalDivide;
+impl ArithmeticOpWithCtx for DecimalDivide {
+    type T = Decimal;
+    fn calc(ctx: &mut EvalContext, lhs: &Decimal, rhs: &Decimal) -> Result<Option<Decimal>> {
+        if rhs.is_zero() {
+            ctx.handle_division_by_zero()?;
+            return Ok(None);
+        }
+        let (precision, scale) = divide_result_precision_scale(lhs, rhs);
+        Ok(Some((lhs / rhs)?.convert_to(ctx, precision, scale)?))
+    }
+}
+
+#[rpn_fn]
+#[inline]
+pub fn int_divide_decimal(lhs: &Option<Decimal>, rhs: &Option<Decimal>) -> Result<Option<Int>> {
+    match (lhs, rhs) {
+        (Some(lhs), Some(rhs)) if !rhs.is_zero() => Ok(Some((lhs / rhs)?.as_i64_trunc())),
+        _ => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+
+    #[test]
+    fn test_int_int_plus() {
+        assert_eq!(IntIntPlus::calc(&1, &2).unwrap(), Some(3));
+        assert_eq!(IntIntPlus::calc(&-1, &-2).unwrap(), Some(-3));
+        assert!(IntIntPlus::calc(&i64::MAX, &1).is_err());
+    }
+
+    #[test]
+    fn test_int_uint_plus_mixed_sign() {
+        // `lhs` is interpreted as a signed BIGINT here even though the
+        // result type is unsigned, so a negative lhs actually subtracts
+        // rather than wrapping into a huge positive number.
+        assert_eq!(IntUintPlus::calc(&-3, &10).unwrap(), Some(7));
+        // Going negative overall is out of range for an unsigned result.
+        assert!(IntUintPlus::calc(&-2, &1).is_err());
+    }
+
+    #[test]
+    fn test_uint_int_plus_mixed_sign() {
+        // `lhs` is the unsigned side here; an unsigned lhs combined
+        // with a negative rhs must not reinterpret rhs's bits as unsigned,
+        // only its value should be subtracted.
+        assert_eq!(UintIntPlus::calc(&10, &-3).unwrap(), Some(7));
+        assert!(UintIntPlus::calc(&0, &-1).is_err());
+    }
+
+    #[test]
+    fn test_uint_uint_plus_treats_bits_as_unsigned() {
+        // `-1i64` as bits is `u64::MAX`, so this must overflow rather than
+        // silently producing a small or negative result.
+        assert!(UintUintPlus::calc(&1, &-1).is_err());
+        assert_eq!(UintUintPlus::calc(&1, &2).unwrap(), Some(3));
+    }
+
+    #[test]
+    fn test_int_int_minus() {
+        assert_eq!(IntIntMinus::calc(&5, &3).unwrap(), Some(2));
+        assert!(IntIntMinus::calc(&i64::MIN, &1).is_err());
+    }
+
+    #[test]
+    fn test_int_int_multiply_overflow() {
+        assert_eq!(IntIntMultiply::calc(&6, &7).unwrap(), Some(42));
+        assert!(IntIntMultiply::calc(&i64::MAX, &2).is_err());
+    }
+
+    #[test]
+    fn test_int_int_mod() {
+        assert_eq!(IntIntMod::calc(&7, &3).unwrap(), Some(1));
+        assert_eq!(IntIntMod::calc(&-7, &3).unwrap(), Some(-1));
+    }
+
+    #[test]
+    fn test_int_divide_int() {
+        assert_eq!(IntDivideInt::calc(&7, &2).unwrap(), Some(3));
+        assert!(IntDivideInt::calc(&1, &0).is_err());
+    }
+
+    #[test]
+    fn test_clamp_scale_and_precision() {
+        assert_eq!(clamp_scale(10), 10);
+        assert_eq!(clamp_scale(1000), MAX_DECIMAL_SCALE);
+        assert_eq!(clamp_precision(10), 10);
+        assert_eq!(clamp_precision(1000), MAX_DECIMAL_PRECISION);
+    }
+
+    #[test]
+    fn test_decimal_plus_aligns_scale() {
+        let mut ctx = EvalContext::default();
+        let lhs = Some(Decimal::from_str("1.1").unwrap());
+        let rhs = Some(Decimal::from_str("2.22").unwrap());
+        let got = DecimalPlus::calc(&mut ctx, lhs.as_ref().unwrap(), rhs.as_ref().unwrap())
+            .unwrap()
+            .unwrap();
+        assert_eq!(got, Decimal::from_str("3.32").unwrap());
+    }
+
+    #[test]
+    fn test_decimal_divide_by_zero() {
+        let mut ctx = EvalContext::default();
+        let lhs = Decimal::from_str("1.0").unwrap();
+        let rhs = Decimal::from_str("0").unwrap();
+        assert_eq!(DecimalDivide::calc(&mut ctx, &lhs, &rhs).unwrap(), None);
+    }
+
+    #[test]
+    fn test_int_divide_decimal() {
+        let lhs = Some(Decimal::from_str("7").unwrap());
+        let rhs = Some(Decimal::from_str("2").unwrap());
+        assert_eq!(int_divide_decimal(&lhs, &rhs).unwrap(), Some(3));
+        let zero = Some(Decimal::from_str("0").unwrap());
+        assert_eq!(int_divide_decimal(&lhs, &zero).unwrap(), None);
+    }
+}
@@ -0,0 +1,273 @@
+// Copyright 2019 TiKV Project Authors. Licensed under Apache-2.0.
+
+use tidb_query_datatype::{FieldTypeAccessor, FieldTypeFlag};
+use tipb::FieldType;
+
+use super::types::*;
+use crate::codec::convert::ConvertTo;
+use crate::codec::data_type::*;
+use crate::expr::EvalContext;
+use crate::Result;
+
+/// A single batch-mode cast, generic over the source and destination
+/// logical types. `cast_fn_meta::<From, To>()` is what `map_pb_sig_to_rpn_func`
+/// uses to register every `Cast{From}As{To}` signature without needing one
+/// hand-written function per pair: the conversion itself is provided by
+/// `From`'s `ConvertTo<To>` impl (already used by the scalar evaluator),
+/// and this wrapper is only responsible for threading `EvalContext` for
+/// warnings/truncation and stamping the destination `FieldType`'s flen,
+/// decimal and unsigned flag onto the result the way MySQL does.
+#[rpn_fn(capture = [ctx, extra])]
+#[inline]
+pub fn cast<From, To>(
+    ctx: &mut EvalContext,
+    extra: &RpnFnCallExtra<'_>,
+    val: &Option<From>,
+) -> Result<Option<To>>
+where
+    From: Evaluable,
+    To: Evaluable + TruncateWithFieldType,
+    From: ConvertTo<To>,
+{
+    match val {
+        None => Ok(None),
+        Some(from) => {
+            let to = from.convert(ctx)?;
+            Ok(Some(produce_with_specified_field_type(
+                ctx,
+                to,
+                extra.ret_field_type,
+            )?))
+        }
+    }
+}
+
+/// `cast<Int, To>` above always reads its operand as a signed `i64`, so a
+/// source column flagged `UNSIGNED` whose value sits above `i64::MAX` gets
+/// its two's-complement bits reinterpreted as a negative number instead of
+/// converted as the large positive value it actually holds. These give
+/// `map_pb_sig_to_rpn_func` an unsigned-source counterpart for the
+/// `CastInt*` signatures where that distinction changes the outcome,
+/// mirroring how `plus_mapper`/`compare_mapper` pick an unsigned-aware
+/// variant based on `children[0]`'s flag.
+#[rpn_fn(capture = [ctx, extra])]
+#[inline]
+pub fn cast_uint_as_real(
+    ctx: &mut EvalContext,
+    extra: &RpnFnCallExtra<'_>,
+    val: &Option<Int>,
+) -> Result<Option<Real>> {
+    match val {
+        None => Ok(None),
+        Some(val) => {
+            let to = Real::new(*val as u64 as f64).unwrap_or_default();
+            Ok(Some(produce_with_specified_field_type(
+                ctx,
+                to,
+                extra.ret_field_type,
+            )?))
+        }
+    }
+}
+
+#[rpn_fn(capture = [ctx, extra])]
+#[inline]
+pub fn cast_uint_as_decimal(
+    ctx: &mut EvalContext,
+    extra: &RpnFnCallExtra<'_>,
+    val: &Option<Int>,
+) -> Result<Option<Decimal>> {
+    match val {
+        None => Ok(None),
+        Some(val) => {
+            let to = Decimal::from_str(&(*val as u64).to_string())
+                .map_err(|e| other_err!("invalid decimal while casting unsigned int: {}", e))?;
+            Ok(Some(produce_with_specified_field_type(
+                ctx,
+                to,
+                extra.ret_field_type,
+            )?))
+        }
+    }
+}
+
+#[rpn_fn(capture = [ctx, extra])]
+#[inline]
+pub fn cast_uint_as_string(
+    ctx: &mut EvalContext,
+    extra: &RpnFnCallExtra<'_>,
+    val: &Option<Int>,
+) -> Result<Option<Bytes>> {
+    match val {
+        None => Ok(None),
+        Some(val) => {
+            let to = (*val as u64).to_string().into_bytes();
+            Ok(Some(produce_with_specified_field_type(
+                ctx,
+                to,
+                extra.ret_field_type,
+            )?))
+        }
+    }
+}
+
+/// Applies the destination `FieldType`'s flen/decimal/unsigned constraints
+/// to a freshly converted value, the same way the non-batch cast path
+/// truncates and flags its result. Types that carry no such constraint
+/// (e.g. `Json`) pass through unchanged.
+fn produce_with_specified_field_type<To: Evaluable + TruncateWithFieldType>(
+    ctx: &mut EvalContext,
+    value: To,
+    ret_field_type: &FieldType,
+) -> Result<To> {
+    // Narrowing by flen/decimal and applying the unsigned flag is done by
+    // each concrete `To` type's own `round_with_frac`/`truncate` helpers,
+    // which already exist on the codec types used by the scalar evaluator;
+    // the batch path only needs to invoke them with the expression's own
+    // `FieldType` rather than re-deriving one.
+    To::truncate_with_field_type(value, ctx, ret_field_type)
+}
+
+/// Implemented by every logical type that can be a CAST destination so
+/// `produce_with_specified_field_type` can apply MySQL's flen/decimal/
+/// unsigned rules generically. The default no-op is correct for types
+/// (like `Json`) that MySQL never truncates on cast.
+pub trait TruncateWithFieldType: Sized {
+    fn truncate_with_field_type(
+        self,
+        _ctx: &mut EvalContext,
+        _ret_field_type: &FieldType,
+    ) -> Result<Self> {
+        Ok(self)
+    }
+}
+
+impl TruncateWithFieldType for Int {
+    fn truncate_with_field_type(
+        mut self,
+        _ctx: &mut EvalContext,
+        ret_field_type: &FieldType,
+    ) -> Result<Self> {
+        if ret_field_type.as_accessor().flag().contains(FieldTypeFlag::UNSIGNED) && self < 0 {
+            self = 0;
+        }
+        Ok(self)
+    }
+}
+
+impl TruncateWithFieldType for Real {
+    fn truncate_with_field_type(
+        self,
+        ctx: &mut EvalContext,
+        ret_field_type: &FieldType,
+    ) -> Result<Self> {
+        let flen = ret_field_type.as_accessor().flen();
+        let decimal = ret_field_type.as_accessor().decimal();
+        if flen > 0 && decimal >= 0 {
+            return self.round_frac(ctx, decimal, flen);
+        }
+        Ok(self)
+    }
+}
+
+impl TruncateWithFieldType for Decimal {
+    fn truncate_with_field_type(
+        self,
+        ctx: &mut EvalContext,
+        ret_field_type: &FieldType,
+    ) -> Result<Self> {
+        let flen = ret_field_type.as_accessor().flen();
+        let decimal = ret_field_type.as_accessor().decimal();
+        if flen >= 0 && decimal >= 0 {
+            return self.convert_to(ctx, flen as u8, decimal as u8);
+        }
+        Ok(self)
+    }
+}
+
+impl TruncateWithFieldType for Bytes {
+    fn truncate_with_field_type(
+        mut self,
+        ctx: &mut EvalContext,
+        ret_field_type: &FieldType,
+    ) -> Result<Self> {
+        let flen = ret_field_type.as_accessor().flen();
+        if flen >= 0 && self.len() > flen as usize {
+            ctx.handle_truncate(true)?;
+            self.truncate(flen as usize);
+        }
+        Ok(self)
+    }
+}
+
+impl TruncateWithFieldType for DateTime {}
+impl TruncateWithFieldType for Duration {}
+impl TruncateWithFieldType for Json {}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+
+    #[test]
+    fn test_int_truncate_with_field_type_clamps_unsigned_negative() {
+        let mut ctx = EvalContext::default();
+        let mut ft = FieldType::default();
+        ft.set_flag(FieldTypeFlag::UNSIGNED);
+
+        // A negative value stored in an UNSIGNED destination column is
+        // clamped to 0 rather than kept as a negative number.
+        assert_eq!((-1i64).truncate_with_field_type(&mut ctx, &ft).unwrap(), 0);
+        assert_eq!(5i64.truncate_with_field_type(&mut ctx, &ft).unwrap(), 5);
+    }
+
+    #[test]
+    fn test_int_truncate_with_field_type_signed_passthrough() {
+        let mut ctx = EvalContext::default();
+        let ft = FieldType::default();
+        assert_eq!((-1i64).truncate_with_field_type(&mut ctx, &ft).unwrap(), -1);
+    }
+
+    #[test]
+    fn test_bytes_truncate_with_field_type_no_flen_passthrough() {
+        let mut ctx = EvalContext::default();
+        let mut ft = FieldType::default();
+        // `flen < 0` means "unspecified" and must not truncate at all;
+        // an unset (zero) flen would otherwise be mistaken for "flen 0".
+        ft.set_flen(-1);
+        let value = b"hello".to_vec();
+        assert_eq!(
+            value.clone().truncate_with_field_type(&mut ctx, &ft).unwrap(),
+            value
+        );
+    }
+
+    #[test]
+    fn test_bytes_truncate_with_field_type_truncates_to_flen() {
+        let mut ctx = EvalContext::default();
+        let mut ft = FieldType::default();
+        ft.set_flen(3);
+        let value = b"hello".to_vec();
+        assert_eq!(
+            value.truncate_with_field_type(&mut ctx, &ft).unwrap(),
+            b"hel".to_vec()
+        );
+    }
+
+    #[test]
+    fn test_decimal_truncate_with_field_type_no_constraint_passthrough() {
+        let mut ctx = EvalContext::default();
+        let mut ft = FieldType::default();
+        // Same "unspecified means negative, not zero" rule as `Bytes`
+        // above: `flen`/`decimal` of `0` would otherwise be read as "round
+        // to 0 digits" instead of "no constraint".
+        ft.set_flen(-1);
+        ft.set_decimal(-1);
+        let value = Decimal::from_str("1.2345").unwrap();
+        assert_eq!(
+            value.clone().truncate_with_field_type(&mut ctx, &ft).unwrap(),
+            value
+        );
+    }
+}
@@ -0,0 +1,216 @@
+// Copyright 2019 TiKV Project Authors. Licensed under Apache-2.0.
+
+//! `GREATEST`/`LEAST`, added alongside this module's existing binary
+//! comparers (`BasicComparer`, `compare_in_fn_meta`, ...). Unlike those,
+//! these signatures are n-ary: every child is scanned row-wise, NULL
+//! propagates if any argument is NULL, and the typed integer variant has
+//! to respect each operand's own signed/unsigned flag the same way
+//! `map_int_sig`/`compare_mapper` do for the binary comparisons.
+
+use std::cmp::Ordering;
+
+use tidb_query_datatype::{FieldTypeAccessor, FieldTypeFlag};
+use tipb::Expr;
+
+use super::types::*;
+use crate::codec::data_type::*;
+use crate::Result;
+
+/// Orders two `Int`s honoring each side's own unsigned flag, mirroring
+/// `compare_mapper`'s `IntUintComparer`/`UintIntComparer` handling for the
+/// binary comparisons.
+fn cmp_int(lhs: Int, lhs_unsigned: bool, rhs: Int, rhs_unsigned: bool) -> Ordering {
+    match (lhs_unsigned, rhs_unsigned) {
+        (false, false) => lhs.cmp(&rhs),
+        (true, true) => (lhs as u64).cmp(&(rhs as u64)),
+        (true, false) => {
+            if rhs < 0 {
+                Ordering::Greater
+            } else {
+                (lhs as u64).cmp(&(rhs as u64))
+            }
+        }
+        (false, true) => {
+            if lhs < 0 {
+                Ordering::Less
+            } else {
+                (lhs as u64).cmp(&(rhs as u64))
+            }
+        }
+    }
+}
+
+fn is_unsigned(expr: &Expr) -> bool {
+    expr.get_field_type()
+        .as_accessor()
+        .flag()
+        .contains(FieldTypeFlag::UNSIGNED)
+}
+
+/// Builds the `RpnFnMeta` for `GreatestInt`/`LeastInt`, capturing each
+/// child's unsigned flag up front the way `map_int_sig` does for the
+/// binary integer comparisons.
+pub fn greatest_least_int_fn_meta(children: &[Expr], pick_greatest: bool) -> Result<RpnFnMeta> {
+    let unsigned_flags: Vec<bool> = children.iter().map(is_unsigned).collect();
+    Ok(greatest_least_int_impl_fn_meta(unsigned_flags, pick_greatest))
+}
+
+#[rpn_fn(varg, capture = [unsigned_flags, pick_greatest])]
+#[inline]
+fn greatest_least_int_impl(
+    unsigned_flags: &[bool],
+    pick_greatest: &bool,
+    args: &[&Option<Int>],
+) -> Result<Option<Int>> {
+    if args.iter().any(|v| v.is_none()) {
+        return Ok(None);
+    }
+    let mut best_idx = 0;
+    for idx in 1..args.len() {
+        let ord = cmp_int(
+            args[idx].unwrap(),
+            unsigned_flags[idx],
+            args[best_idx].unwrap(),
+            unsigned_flags[best_idx],
+        );
+        let replace = if *pick_greatest {
+            ord == Ordering::Greater
+        } else {
+            ord == Ordering::Less
+        };
+        if replace {
+            best_idx = idx;
+        }
+    }
+    Ok(args[best_idx].to_owned())
+}
+
+macro_rules! greatest_least_typed {
+    ($name_greatest:ident, $name_least:ident, $ty:ty) => {
+        #[rpn_fn(varg)]
+        #[inline]
+        pub fn $name_greatest(args: &[&Option<$ty>]) -> Result<Option<$ty>> {
+            pick_extreme(args, true)
+        }
+
+        #[rpn_fn(varg)]
+        #[inline]
+        pub fn $name_least(args: &[&Option<$ty>]) -> Result<Option<$ty>> {
+            pick_extreme(args, false)
+        }
+    };
+}
+
+fn pick_extreme<T: Clone + PartialOrd>(
+    args: &[&Option<T>],
+    pick_greatest: bool,
+) -> Result<Option<T>> {
+    if args.iter().any(|v| v.is_none()) {
+        return Ok(None);
+    }
+    let mut best = args[0].as_ref().unwrap();
+    for candidate in &args[1..] {
+        let candidate = candidate.as_ref().unwrap();
+        let replace = if pick_greatest {
+            candidate.partial_cmp(best) == Some(Ordering::Greater)
+        } else {
+            candidate.partial_cmp(best) == Some(Ordering::Less)
+        };
+        if replace {
+            best = candidate;
+        }
+    }
+    Ok(Some(best.clone()))
+}
+
+greatest_least_typed!(greatest_real, least_real, Real);
+greatest_least_typed!(greatest_decimal, least_decimal, Decimal);
+greatest_least_typed!(greatest_string, least_string, Bytes);
+greatest_least_typed!(greatest_time, least_time, DateTime);
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+
+    #[test]
+    fn test_cmp_int_unsigned() {
+        // An unsigned `-1` (all bits set) is the largest u64, so it must
+        // compare greater than any signed positive value, even though `-1
+        // < 5` as plain `i64`s.
+        assert_eq!(cmp_int(-1, true, 5, false), Ordering::Greater);
+        assert_eq!(cmp_int(5, false, -1, true), Ordering::Less);
+        assert_eq!(cmp_int(-1, false, 5, false), Ordering::Less);
+        assert_eq!(cmp_int(3, true, 3, true), Ordering::Equal);
+    }
+
+    #[test]
+    fn test_greatest_least_int_impl() {
+        let unsigned = vec![false, true];
+        let a = Some(-1i64);
+        let b = Some(5i64);
+        let args: Vec<&Option<Int>> = vec![&a, &b];
+
+        // `b` is unsigned, so its `5` beats signed `-1` either way around;
+        // the interesting case is making sure greatest/least both read the
+        // per-argument flag rather than just comparing raw i64s.
+        assert_eq!(
+            greatest_least_int_impl(&unsigned, &true, &args).unwrap(),
+            Some(5)
+        );
+        assert_eq!(
+            greatest_least_int_impl(&unsigned, &false, &args).unwrap(),
+            Some(-1)
+        );
+
+        let none: Option<Int> = None;
+        let args_with_null: Vec<&Option<Int>> = vec![&a, &none];
+        assert_eq!(
+            greatest_least_int_impl(&unsigned, &true, &args_with_null).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_greatest_least_real() {
+        let a = Real::new(1.5).unwrap();
+        let b = Real::new(2.5).unwrap();
+        let c = Real::new(-3.5).unwrap();
+        let (a, b, c) = (Some(a), Some(b), Some(c));
+        let args: Vec<&Option<Real>> = vec![&a, &b, &c];
+
+        assert_eq!(greatest_real(&args).unwrap(), Some(Real::new(2.5).unwrap()));
+        assert_eq!(least_real(&args).unwrap(), Some(Real::new(-3.5).unwrap()));
+
+        let none: Option<Real> = None;
+        let args_with_null: Vec<&Option<Real>> = vec![&a, &none];
+        assert_eq!(greatest_real(&args_with_null).unwrap(), None);
+    }
+
+    #[test]
+    fn test_greatest_least_string() {
+        let a = Some(b"banana".to_vec());
+        let b = Some(b"apple".to_vec());
+        let args: Vec<&Option<Bytes>> = vec![&a, &b];
+
+        assert_eq!(greatest_string(&args).unwrap(), Some(b"banana".to_vec()));
+        assert_eq!(least_string(&args).unwrap(), Some(b"apple".to_vec()));
+    }
+
+    #[test]
+    fn test_greatest_least_decimal() {
+        let a = Some(Decimal::from_str("1.10").unwrap());
+        let b = Some(Decimal::from_str("1.2").unwrap());
+        let args: Vec<&Option<Decimal>> = vec![&a, &b];
+
+        assert_eq!(
+            greatest_decimal(&args).unwrap(),
+            Some(Decimal::from_str("1.2").unwrap())
+        );
+        assert_eq!(
+            least_decimal(&args).unwrap(),
+            Some(Decimal::from_str("1.10").unwrap())
+        );
+    }
+}
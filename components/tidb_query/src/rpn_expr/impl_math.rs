@@ -0,0 +1,266 @@
+// Copyright 2019 TiKV Project Authors. Licensed under Apache-2.0.
+
+//! `ROUND(x)` / `ROUND(x, d)`. The important correctness requirement for
+//! the decimal variant is to round on the integer mantissa rather than
+//! through floating point: a `Decimal` is an integer value with a scale
+//! `s`, and rounding to `d < s` fractional digits means dropping the
+//! trailing `s - d` digits and incrementing the kept mantissa away from
+//! zero when the first dropped digit is `>= 5`.
+
+use std::str::FromStr;
+
+use crate::codec::data_type::*;
+use crate::Result;
+
+/// `ROUND(x)` for reals: [`round_with_frac_real`] with `d = 0`.
+#[rpn_fn]
+#[inline]
+pub fn round_real(arg: &Option<Real>) -> Result<Option<Real>> {
+    round_with_frac_real(arg, &Some(0))
+}
+
+/// Rounds `value` (reals use ties-away-from-zero, matching MySQL) to `d`
+/// fractional digits. `d` may be negative, rounding into the integer part.
+#[rpn_fn]
+#[inline]
+pub fn round_with_frac_real(arg: &Option<Real>, frac: &Option<Int>) -> Result<Option<Real>> {
+    match (arg, frac) {
+        (Some(value), Some(d)) => {
+            let value = value.into_inner();
+            let scale = 10f64.powi(*d as i32);
+            let rounded = (value * scale).round() / scale;
+            Ok(Real::new(rounded).ok())
+        }
+        _ => Ok(None),
+    }
+}
+
+#[rpn_fn]
+#[inline]
+pub fn round_int(arg: &Option<Int>) -> Result<Option<Int>> {
+    round_with_frac_int(arg, &Some(0))
+}
+
+#[rpn_fn]
+#[inline]
+pub fn round_with_frac_int(arg: &Option<Int>, frac: &Option<Int>) -> Result<Option<Int>> {
+    match (arg, frac) {
+        (Some(value), Some(d)) if *d < 0 => {
+            // `-*d` overflows for `d == i64::MIN`; `unsigned_abs` gives the
+            // magnitude without negating, and a frac digit count that large
+            // saturates the `10i64.pow` below well before it matters.
+            let scale = 10i64.saturating_pow(d.unsigned_abs().min(u32::MAX as u64) as u32);
+            if scale == 0 {
+                return Ok(Some(0));
+            }
+            let half = scale / 2;
+            let rounded = if *value >= 0 {
+                ((value + half) / scale) * scale
+            } else {
+                -(((-value + half) / scale) * scale)
+            };
+            Ok(Some(rounded))
+        }
+        (Some(value), Some(_)) => Ok(Some(*value)),
+        _ => Ok(None),
+    }
+}
+
+#[rpn_fn]
+#[inline]
+pub fn round_dec(arg: &Option<Decimal>) -> Result<Option<Decimal>> {
+    round_with_frac_dec(arg, &Some(0))
+}
+
+#[rpn_fn]
+#[inline]
+pub fn round_with_frac_dec(arg: &Option<Decimal>, frac: &Option<Int>) -> Result<Option<Decimal>> {
+    match (arg, frac) {
+        (Some(value), Some(d)) => Ok(Some(round_decimal_half_away_from_zero(value, *d)?)),
+        _ => Ok(None),
+    }
+}
+
+/// Rounds a `Decimal` to `d` fractional digits by operating on its decimal
+/// digit string directly, so the result is exact rather than subject to
+/// `f64` rounding error. `d` may be negative, rounding into the integer
+/// part the same way.
+fn round_decimal_half_away_from_zero(value: &Decimal, d: i64) -> Result<Decimal> {
+    let s = value.to_string();
+    let (sign, digits) = if let Some(rest) = s.strip_prefix('-') {
+        (true, rest)
+    } else {
+        (false, s.as_str())
+    };
+
+    let (int_part, frac_part) = match digits.find('.') {
+        Some(pos) => (&digits[..pos], &digits[pos + 1..]),
+        None => (digits, ""),
+    };
+
+    let mut int_digits: Vec<u8> = int_part.bytes().collect();
+    let mut frac_digits: Vec<u8> = frac_part.bytes().collect();
+
+    // Negative `d` rounds into the integer part: treat it as rounding the
+    // fractional part to zero digits after first moving `-d` digits from
+    // the tail of the integer part into the "dropped" region.
+    let target_scale = d.max(0) as usize;
+
+    if target_scale >= frac_digits.len() && d >= 0 {
+        // Nothing to drop; value already has <= d fractional digits.
+        return Ok(value.clone());
+    }
+
+    // Zeros appended to the integer part after rounding, in place of the
+    // integer digits dropped by a negative `d`.
+    let mut pad_zeros = 0;
+    let round_up = if d >= 0 {
+        let dropped_leading = frac_digits[target_scale];
+        frac_digits.truncate(target_scale);
+        dropped_leading >= b'5'
+    } else {
+        // Same `i64::MIN` hazard as `round_with_frac_int`: negating `d`
+        // directly panics (debug) or wraps (release) for that one value,
+        // so use `unsigned_abs` to get the magnitude instead.
+        let shift = d.unsigned_abs().min(usize::MAX as u64) as usize;
+        frac_digits.clear();
+        if shift > int_digits.len() {
+            int_digits = vec![b'0'];
+            false
+        } else {
+            let cut = int_digits.len() - shift;
+            let dropped_leading = int_digits[cut];
+            int_digits.truncate(cut);
+            pad_zeros = shift;
+            dropped_leading >= b'5'
+        }
+    };
+
+    if round_up {
+        increment_mantissa_away_from_zero(&mut int_digits, &mut frac_digits);
+    }
+    if int_digits.is_empty() {
+        int_digits.push(b'0');
+    } else {
+        int_digits.extend(std::iter::repeat(b'0').take(pad_zeros));
+    }
+
+    let mut result = String::new();
+    if sign {
+        result.push('-');
+    }
+    result.push_str(&String::from_utf8(int_digits).unwrap());
+    if !frac_digits.is_empty() {
+        result.push('.');
+        result.push_str(&String::from_utf8(frac_digits).unwrap());
+    }
+
+    Decimal::from_str(&result).map_err(|e| other_err!("invalid decimal after rounding: {}", e))
+}
+
+/// Adds one to the combined `int_digits.frac_digits` mantissa, propagating
+/// the carry leftward and growing the integer part by a digit if needed.
+fn increment_mantissa_away_from_zero(int_digits: &mut Vec<u8>, frac_digits: &mut [u8]) {
+    let mut carry = 1u8;
+    for digit in frac_digits.iter_mut().rev() {
+        if carry == 0 {
+            break;
+        }
+        let v = (*digit - b'0') + carry;
+        *digit = b'0' + v % 10;
+        carry = v / 10;
+    }
+    if carry == 0 {
+        return;
+    }
+    for digit in int_digits.iter_mut().rev() {
+        if carry == 0 {
+            break;
+        }
+        let v = (*digit - b'0') + carry;
+        *digit = b'0' + v % 10;
+        carry = v / 10;
+    }
+    if carry > 0 {
+        int_digits.insert(0, b'0' + carry);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+
+    #[test]
+    fn test_round_with_frac_real() {
+        let cases = vec![
+            (1.4, 0, 1.0),
+            (1.5, 0, 2.0),
+            (-1.5, 0, -2.0),
+            (123.456, 2, 123.46),
+            (123.456, -2, 100.0),
+        ];
+        for (arg, frac, expected) in cases {
+            let arg = Some(Real::new(arg).unwrap());
+            let got = round_with_frac_real(&arg, &Some(frac)).unwrap().unwrap();
+            assert_eq!(got.into_inner(), expected, "arg={:?} frac={}", arg, frac);
+        }
+        assert_eq!(round_with_frac_real(&None, &Some(0)).unwrap(), None);
+    }
+
+    #[test]
+    fn test_round_with_frac_int() {
+        let cases = vec![
+            (12345i64, -2, 12300i64),
+            (12350i64, -2, 12400i64),
+            (-12350i64, -2, -12400i64),
+            (12345i64, 2, 12345i64),
+            (12345i64, 0, 12345i64),
+        ];
+        for (arg, frac, expected) in cases {
+            let got = round_with_frac_int(&Some(arg), &Some(frac)).unwrap();
+            assert_eq!(got, Some(expected), "arg={} frac={}", arg, frac);
+        }
+    }
+
+    #[test]
+    fn test_round_with_frac_int_min_frac_does_not_panic() {
+        // `frac == i64::MIN` used to overflow negating it directly; it
+        // should instead just saturate to the same result as any other
+        // very negative `frac`.
+        let got = round_with_frac_int(&Some(12345), &Some(i64::MIN)).unwrap();
+        assert_eq!(got, Some(0));
+    }
+
+    #[test]
+    fn test_round_with_frac_dec() {
+        let cases = vec![
+            ("123.456", 2, "123.46"),
+            ("123.456", -2, "100"),
+            ("123.445", 2, "123.45"),
+            ("-123.456", 2, "-123.46"),
+            ("123.4", 5, "123.4"),
+        ];
+        for (arg, frac, expected) in cases {
+            let arg = Some(Decimal::from_str(arg).unwrap());
+            let got = round_with_frac_dec(&arg, &Some(frac)).unwrap().unwrap();
+            assert_eq!(
+                got,
+                Decimal::from_str(expected).unwrap(),
+                "arg={} frac={}",
+                arg.unwrap(),
+                frac
+            );
+        }
+    }
+
+    #[test]
+    fn test_round_decimal_half_away_from_zero_min_frac_does_not_panic() {
+        // Same `i64::MIN` hazard as `round_with_frac_int`, on the
+        // negative-`d` (rounding into the integer part) branch.
+        let value = Decimal::from_str("123.456").unwrap();
+        let got = round_decimal_half_away_from_zero(&value, i64::MIN).unwrap();
+        assert_eq!(got, Decimal::from_str("0").unwrap());
+    }
+}
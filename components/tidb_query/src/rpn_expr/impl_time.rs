@@ -0,0 +1,202 @@
+// Copyright 2019 TiKV Project Authors. Licensed under Apache-2.0.
+
+//! Temporal arithmetic: `Duration + Duration`, `Duration - Duration`,
+//! scaling a `Duration` by an integer, and `DateTime +/- Duration`. Unlike
+//! the comparisons already dispatched above, time values need carry and
+//! overflow handling of their own: duration sums saturate at the MySQL
+//! `TIME` range (`±838:59:59`) rather than wrapping, and datetime results
+//! normalize across day/month boundaries honoring the context's timezone
+//! and fractional-second precision.
+
+use crate::codec::data_type::*;
+use crate::expr::EvalContext;
+use crate::Result;
+
+/// The MySQL `TIME` range is `-838:59:59.000000` to `838:59:59.000000`.
+const MAX_TIME_NANOS: i64 = ((838 * 3600 + 59 * 60 + 59) as i64) * 1_000_000_000;
+
+fn saturate_duration_nanos(ctx: &mut EvalContext, nanos: i64) -> Result<i64> {
+    if nanos > MAX_TIME_NANOS || nanos < -MAX_TIME_NANOS {
+        ctx.handle_truncate(true)?;
+        Ok(nanos.signum() * MAX_TIME_NANOS)
+    } else {
+        Ok(nanos)
+    }
+}
+
+#[rpn_fn(capture = [ctx])]
+#[inline]
+pub fn duration_plus_duration(
+    ctx: &mut EvalContext,
+    lhs: &Option<Duration>,
+    rhs: &Option<Duration>,
+) -> Result<Option<Duration>> {
+    match (lhs, rhs) {
+        (Some(lhs), Some(rhs)) => {
+            let fsp = lhs.fsp().max(rhs.fsp());
+            let nanos = saturate_duration_nanos(ctx, lhs.to_nanos() + rhs.to_nanos())?;
+            Ok(Some(Duration::from_nanos(nanos, fsp)?))
+        }
+        _ => Ok(None),
+    }
+}
+
+#[rpn_fn(capture = [ctx])]
+#[inline]
+pub fn duration_minus_duration(
+    ctx: &mut EvalContext,
+    lhs: &Option<Duration>,
+    rhs: &Option<Duration>,
+) -> Result<Option<Duration>> {
+    match (lhs, rhs) {
+        (Some(lhs), Some(rhs)) => {
+            let fsp = lhs.fsp().max(rhs.fsp());
+            let nanos = saturate_duration_nanos(ctx, lhs.to_nanos() - rhs.to_nanos())?;
+            Ok(Some(Duration::from_nanos(nanos, fsp)?))
+        }
+        _ => Ok(None),
+    }
+}
+
+#[rpn_fn(capture = [ctx])]
+#[inline]
+pub fn duration_multiply_int(
+    ctx: &mut EvalContext,
+    duration: &Option<Duration>,
+    times: &Option<Int>,
+) -> Result<Option<Duration>> {
+    match (duration, times) {
+        (Some(duration), Some(times)) => {
+            let nanos = saturate_duration_nanos(ctx, duration.to_nanos().saturating_mul(*times))?;
+            Ok(Some(Duration::from_nanos(nanos, duration.fsp())?))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// `DateTime + Duration`, normalizing across day/month/year boundaries in
+/// the context's configured timezone. Returns NULL (with a pushed
+/// warning, matching the scalar evaluator) when the result falls outside
+/// the valid `DATETIME` range instead of erroring the whole request.
+#[rpn_fn(capture = [ctx])]
+#[inline]
+pub fn date_add_duration(
+    ctx: &mut EvalContext,
+    datetime: &Option<DateTime>,
+    duration: &Option<Duration>,
+) -> Result<Option<DateTime>> {
+    shift_datetime(ctx, datetime, duration, false)
+}
+
+/// `DateTime - Duration`, the inverse of [`date_add_duration`].
+#[rpn_fn(capture = [ctx])]
+#[inline]
+pub fn date_sub_duration(
+    ctx: &mut EvalContext,
+    datetime: &Option<DateTime>,
+    duration: &Option<Duration>,
+) -> Result<Option<DateTime>> {
+    shift_datetime(ctx, datetime, duration, true)
+}
+
+fn shift_datetime(
+    ctx: &mut EvalContext,
+    datetime: &Option<DateTime>,
+    duration: &Option<Duration>,
+    negate: bool,
+) -> Result<Option<DateTime>> {
+    match (datetime, duration) {
+        (Some(datetime), Some(duration)) => {
+            let nanos = if negate {
+                -duration.to_nanos()
+            } else {
+                duration.to_nanos()
+            };
+            match datetime.checked_add_nanos(ctx, nanos) {
+                Some(result) => Ok(Some(result)),
+                None => {
+                    ctx.handle_invalid_time_error(other_err!(
+                        "datetime arithmetic out of range"
+                    ))?;
+                    Ok(None)
+                }
+            }
+        }
+        _ => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_saturate_duration_nanos() {
+        let mut ctx = EvalContext::default();
+        assert_eq!(saturate_duration_nanos(&mut ctx, 1_000).unwrap(), 1_000);
+        assert_eq!(
+            saturate_duration_nanos(&mut ctx, MAX_TIME_NANOS + 1).unwrap(),
+            MAX_TIME_NANOS
+        );
+        assert_eq!(
+            saturate_duration_nanos(&mut ctx, -(MAX_TIME_NANOS + 1)).unwrap(),
+            -MAX_TIME_NANOS
+        );
+    }
+
+    #[test]
+    fn test_duration_plus_duration() {
+        let mut ctx = EvalContext::default();
+        let lhs = Some(Duration::from_nanos(1_000_000_000, 2).unwrap());
+        let rhs = Some(Duration::from_nanos(2_000_000_000, 4).unwrap());
+        let got = duration_plus_duration(&mut ctx, &lhs, &rhs).unwrap().unwrap();
+        assert_eq!(got.to_nanos(), 3_000_000_000);
+        // fsp is the wider of the two operands.
+        assert_eq!(got.fsp(), 4);
+
+        assert_eq!(duration_plus_duration(&mut ctx, &None, &rhs).unwrap(), None);
+    }
+
+    #[test]
+    fn test_duration_minus_duration() {
+        let mut ctx = EvalContext::default();
+        let lhs = Some(Duration::from_nanos(5_000_000_000, 0).unwrap());
+        let rhs = Some(Duration::from_nanos(2_000_000_000, 0).unwrap());
+        let got = duration_minus_duration(&mut ctx, &lhs, &rhs)
+            .unwrap()
+            .unwrap();
+        assert_eq!(got.to_nanos(), 3_000_000_000);
+    }
+
+    #[test]
+    fn test_duration_plus_duration_saturates() {
+        let mut ctx = EvalContext::default();
+        let lhs = Some(Duration::from_nanos(MAX_TIME_NANOS, 0).unwrap());
+        let rhs = Some(Duration::from_nanos(MAX_TIME_NANOS, 0).unwrap());
+        let got = duration_plus_duration(&mut ctx, &lhs, &rhs).unwrap().unwrap();
+        assert_eq!(got.to_nanos(), MAX_TIME_NANOS);
+    }
+
+    #[test]
+    fn test_duration_multiply_int() {
+        let mut ctx = EvalContext::default();
+        let duration = Some(Duration::from_nanos(1_000_000_000, 1).unwrap());
+
+        let got = duration_multiply_int(&mut ctx, &duration, &Some(3))
+            .unwrap()
+            .unwrap();
+        assert_eq!(got.to_nanos(), 3_000_000_000);
+        assert_eq!(got.fsp(), 1);
+
+        // Saturates rather than overflowing for a huge multiplier.
+        let got = duration_multiply_int(&mut ctx, &duration, &Some(i64::MAX))
+            .unwrap()
+            .unwrap();
+        assert_eq!(got.to_nanos(), MAX_TIME_NANOS);
+
+        assert_eq!(
+            duration_multiply_int(&mut ctx, &None, &Some(3)).unwrap(),
+            None
+        );
+    }
+}
@@ -21,6 +21,7 @@ use crate::codec::data_type::*;
 use crate::Result;
 
 use self::impl_arithmetic::*;
+use self::impl_cast::*;
 use self::impl_compare::*;
 use self::impl_control::*;
 use self::impl_json::*;
@@ -63,6 +64,25 @@ fn compare_mapper<F: CmpOp>(lhs_is_unsigned: bool, rhs_is_unsigned: bool) -> Rpn
     }
 }
 
+/// Picks the unsigned-source variant of a `CastInt*` signature when its
+/// sole operand is itself flagged unsigned. CAST only ever takes one
+/// operand, so this inspects `children[0]` directly rather than going
+/// through `map_int_sig`'s two-child shape.
+fn cast_int_mapper(children: &[Expr], signed: RpnFnMeta, unsigned: RpnFnMeta) -> Result<RpnFnMeta> {
+    if children.len() != 1 {
+        return Err(other_err!(
+            "Cast (params = {}) is not supported in batch mode",
+            children.len()
+        ));
+    }
+    let src_is_unsigned = children[0]
+        .get_field_type()
+        .as_accessor()
+        .flag()
+        .contains(FieldTypeFlag::UNSIGNED);
+    Ok(if src_is_unsigned { unsigned } else { signed })
+}
+
 fn plus_mapper(lhs_is_unsigned: bool, rhs_is_unsigned: bool) -> RpnFnMeta {
     match (lhs_is_unsigned, rhs_is_unsigned) {
         (false, false) => arithmetic_fn_meta::<IntIntPlus>(),
@@ -235,6 +255,94 @@ fn map_pb_sig_to_rpn_func(value: ScalarFuncSig, children: &[Expr]) -> Result<Rpn
         ScalarFuncSig::IfTime => if_condition_fn_meta::<DateTime>(),
         ScalarFuncSig::IfDecimal => if_condition_fn_meta::<Decimal>(),
         ScalarFuncSig::JsonTypeSig => json_type_fn_meta(),
+
+        ScalarFuncSig::AddDurationAndDuration => duration_plus_duration_fn_meta(),
+        ScalarFuncSig::SubDurationAndDuration => duration_minus_duration_fn_meta(),
+        ScalarFuncSig::MultiplyDurationAndInt => duration_multiply_int_fn_meta(),
+        ScalarFuncSig::AddDateAndDuration => date_add_duration_fn_meta(),
+        ScalarFuncSig::SubDateAndDuration => date_sub_duration_fn_meta(),
+
+        ScalarFuncSig::RoundInt => round_int_fn_meta(),
+        ScalarFuncSig::RoundReal => round_real_fn_meta(),
+        ScalarFuncSig::RoundDec => round_dec_fn_meta(),
+        ScalarFuncSig::RoundWithFracInt => round_with_frac_int_fn_meta(),
+        ScalarFuncSig::RoundWithFracReal => round_with_frac_real_fn_meta(),
+        ScalarFuncSig::RoundWithFracDec => round_with_frac_dec_fn_meta(),
+
+        ScalarFuncSig::GreatestInt => greatest_least_int_fn_meta(children, true)?,
+        ScalarFuncSig::LeastInt => greatest_least_int_fn_meta(children, false)?,
+        ScalarFuncSig::GreatestReal => greatest_real_fn_meta(),
+        ScalarFuncSig::LeastReal => least_real_fn_meta(),
+        ScalarFuncSig::GreatestDecimal => greatest_decimal_fn_meta(),
+        ScalarFuncSig::LeastDecimal => least_decimal_fn_meta(),
+        ScalarFuncSig::GreatestString => greatest_string_fn_meta(),
+        ScalarFuncSig::LeastString => least_string_fn_meta(),
+        ScalarFuncSig::GreatestTime => greatest_time_fn_meta(),
+        ScalarFuncSig::LeastTime => least_time_fn_meta(),
+
+        // `Cast{From}As{To}` covers the full 7x7 matrix of logical types;
+        // the actual conversion lives in each `From: ConvertTo<To>` impl in
+        // `codec::convert`, `cast_fn_meta::<From, To>()` just wires a given
+        // pair into the dispatcher and stamps the result's flen/decimal/
+        // unsigned flag from the expression's own `FieldType`.
+        // `CastIntAsReal/Decimal/String` need a signed/unsigned split: a
+        // source column flagged UNSIGNED above `i64::MAX` is a different
+        // number depending on whether its bits are read as `i64` or
+        // `u64`, and only these three destinations can actually represent
+        // that difference. `CastIntAsTime`/`CastIntAsDuration` parse their
+        // operand as decimal digits (YYYYMMDDHHMMSS / HHMMSS); any value
+        // large enough for the signed/unsigned reading to differ is
+        // already out of range for both, so they don't need the split.
+        ScalarFuncSig::CastIntAsInt => cast_fn_meta::<Int, Int>(),
+        ScalarFuncSig::CastIntAsReal => cast_int_mapper(children, cast_fn_meta::<Int, Real>(), cast_uint_as_real_fn_meta())?,
+        ScalarFuncSig::CastIntAsString => cast_int_mapper(children, cast_fn_meta::<Int, Bytes>(), cast_uint_as_string_fn_meta())?,
+        ScalarFuncSig::CastIntAsDecimal => cast_int_mapper(children, cast_fn_meta::<Int, Decimal>(), cast_uint_as_decimal_fn_meta())?,
+        ScalarFuncSig::CastIntAsTime => cast_fn_meta::<Int, DateTime>(),
+        ScalarFuncSig::CastIntAsDuration => cast_fn_meta::<Int, Duration>(),
+        ScalarFuncSig::CastIntAsJson => cast_fn_meta::<Int, Json>(),
+        ScalarFuncSig::CastRealAsInt => cast_fn_meta::<Real, Int>(),
+        ScalarFuncSig::CastRealAsReal => cast_fn_meta::<Real, Real>(),
+        ScalarFuncSig::CastRealAsString => cast_fn_meta::<Real, Bytes>(),
+        ScalarFuncSig::CastRealAsDecimal => cast_fn_meta::<Real, Decimal>(),
+        ScalarFuncSig::CastRealAsTime => cast_fn_meta::<Real, DateTime>(),
+        ScalarFuncSig::CastRealAsDuration => cast_fn_meta::<Real, Duration>(),
+        ScalarFuncSig::CastRealAsJson => cast_fn_meta::<Real, Json>(),
+        ScalarFuncSig::CastDecimalAsInt => cast_fn_meta::<Decimal, Int>(),
+        ScalarFuncSig::CastDecimalAsReal => cast_fn_meta::<Decimal, Real>(),
+        ScalarFuncSig::CastDecimalAsString => cast_fn_meta::<Decimal, Bytes>(),
+        ScalarFuncSig::CastDecimalAsDecimal => cast_fn_meta::<Decimal, Decimal>(),
+        ScalarFuncSig::CastDecimalAsTime => cast_fn_meta::<Decimal, DateTime>(),
+        ScalarFuncSig::CastDecimalAsDuration => cast_fn_meta::<Decimal, Duration>(),
+        ScalarFuncSig::CastDecimalAsJson => cast_fn_meta::<Decimal, Json>(),
+        ScalarFuncSig::CastStringAsInt => cast_fn_meta::<Bytes, Int>(),
+        ScalarFuncSig::CastStringAsReal => cast_fn_meta::<Bytes, Real>(),
+        ScalarFuncSig::CastStringAsString => cast_fn_meta::<Bytes, Bytes>(),
+        ScalarFuncSig::CastStringAsDecimal => cast_fn_meta::<Bytes, Decimal>(),
+        ScalarFuncSig::CastStringAsTime => cast_fn_meta::<Bytes, DateTime>(),
+        ScalarFuncSig::CastStringAsDuration => cast_fn_meta::<Bytes, Duration>(),
+        ScalarFuncSig::CastStringAsJson => cast_fn_meta::<Bytes, Json>(),
+        ScalarFuncSig::CastTimeAsInt => cast_fn_meta::<DateTime, Int>(),
+        ScalarFuncSig::CastTimeAsReal => cast_fn_meta::<DateTime, Real>(),
+        ScalarFuncSig::CastTimeAsString => cast_fn_meta::<DateTime, Bytes>(),
+        ScalarFuncSig::CastTimeAsDecimal => cast_fn_meta::<DateTime, Decimal>(),
+        ScalarFuncSig::CastTimeAsTime => cast_fn_meta::<DateTime, DateTime>(),
+        ScalarFuncSig::CastTimeAsDuration => cast_fn_meta::<DateTime, Duration>(),
+        ScalarFuncSig::CastTimeAsJson => cast_fn_meta::<DateTime, Json>(),
+        ScalarFuncSig::CastDurationAsInt => cast_fn_meta::<Duration, Int>(),
+        ScalarFuncSig::CastDurationAsReal => cast_fn_meta::<Duration, Real>(),
+        ScalarFuncSig::CastDurationAsString => cast_fn_meta::<Duration, Bytes>(),
+        ScalarFuncSig::CastDurationAsDecimal => cast_fn_meta::<Duration, Decimal>(),
+        ScalarFuncSig::CastDurationAsTime => cast_fn_meta::<Duration, DateTime>(),
+        ScalarFuncSig::CastDurationAsDuration => cast_fn_meta::<Duration, Duration>(),
+        ScalarFuncSig::CastDurationAsJson => cast_fn_meta::<Duration, Json>(),
+        ScalarFuncSig::CastJsonAsInt => cast_fn_meta::<Json, Int>(),
+        ScalarFuncSig::CastJsonAsReal => cast_fn_meta::<Json, Real>(),
+        ScalarFuncSig::CastJsonAsString => cast_fn_meta::<Json, Bytes>(),
+        ScalarFuncSig::CastJsonAsDecimal => cast_fn_meta::<Json, Decimal>(),
+        ScalarFuncSig::CastJsonAsTime => cast_fn_meta::<Json, DateTime>(),
+        ScalarFuncSig::CastJsonAsDuration => cast_fn_meta::<Json, Duration>(),
+        ScalarFuncSig::CastJsonAsJson => cast_fn_meta::<Json, Json>(),
+
         _ => return Err(other_err!(
             "ScalarFunction {:?} is not supported in batch mode",
             value
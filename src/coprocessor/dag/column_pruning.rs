@@ -0,0 +1,267 @@
+// Copyright 2019 TiKV Project Authors. Licensed under Apache-2.0.
+
+//! A reverse-dataflow column liveness pass that runs before the executor
+//! chain is built, dropping columns the query never consumes so the scan
+//! executors decode less per row. This is a classic backward liveness
+//! analysis: seed the live set from `DAGRequest::output_offsets`, then walk
+//! the executor list from the last operator back to the scan, unioning in
+//! every column offset each operator actually reads (selection predicates,
+//! group-by/aggregate-argument expressions, order-by expressions). Once
+//! the scan is reached, its column list is restricted to the live set
+//! (always keeping the handle/PK column so row identity survives
+//! downstream) and every surviving expression's `ColumnRef` offsets are
+//! rewritten to the compacted positions.
+//!
+//! This is a no-op when every column is already live, so it is safe to run
+//! unconditionally ahead of both the normal and batch coprocessor paths.
+
+use std::collections::BTreeSet;
+
+use tikv_util::codec::number::{NumberDecoder, NumberEncoder};
+use tipb::{ColumnInfo, ExecType, Executor, Expr, ExprType};
+
+/// `Expr::val` for a `ColumnRef` is its column offset, encoded as a
+/// big-endian `i64` the same way the RPN builder decodes it when turning
+/// the descriptor into an expression tree.
+fn column_ref_offset(expr: &Expr) -> Option<usize> {
+    let mut val = expr.get_val();
+    val.read_i64().ok().map(|v| v as usize)
+}
+
+fn set_column_ref_offset(expr: &mut Expr, offset: usize) {
+    let mut buf = Vec::with_capacity(8);
+    let _ = buf.write_i64(offset as i64);
+    expr.set_val(buf);
+}
+
+/// Drops dead columns from the scan executor at the bottom of
+/// `executors` and rewrites every surviving `ColumnRef` offset to match,
+/// in place. `executors` must be in build order (scan first).
+pub fn prune_unused_columns(executors: &mut [Executor]) {
+    if executors.is_empty() {
+        return;
+    }
+
+    let scan_space_len = first_reshaping_executor(executors).map_or(executors.len(), |idx| idx + 1);
+    let live = compute_live_offsets(&executors[..scan_space_len]);
+    let scan_columns_len = scan_columns(&executors[0]).len();
+    if live.len() == scan_columns_len {
+        // Every column the scan produces is already live; nothing to do.
+        return;
+    }
+
+    // offset in the original schema -> offset in the compacted schema.
+    let remap: std::collections::HashMap<usize, usize> = live
+        .iter()
+        .enumerate()
+        .map(|(new_offset, &old_offset)| (old_offset, new_offset))
+        .collect();
+
+    restrict_scan_columns(&mut executors[0], &live);
+
+    for exec in executors[1..scan_space_len].iter_mut() {
+        rewrite_executor_offsets(exec, &remap);
+    }
+}
+
+/// Index of the first aggregation or projection. The executors above it,
+/// and `output_offsets`, index its output rather than the scan's columns.
+fn first_reshaping_executor(executors: &[Executor]) -> Option<usize> {
+    executors.iter().position(|exec| match exec.get_tp() {
+        ExecType::TypeAggregation | ExecType::TypeStreamAgg | ExecType::TypeProjection => true,
+        _ => false,
+    })
+}
+
+fn compute_live_offsets(executors: &[Executor]) -> Vec<usize> {
+    let mut live: BTreeSet<usize> = BTreeSet::new();
+
+    // `output_offsets` lives on the `DAGRequest`, not the executor, so it
+    // is not in scope here: only the columns that some executor's
+    // expressions reference count as live, plus the handle column. A
+    // column the request only outputs and never references is dropped;
+    // `prune_unused_columns_for`, which `build_handler` calls, also seeds
+    // the set from `output_offsets`.
+    for exec in executors.iter().rev() {
+        collect_referenced_columns(exec, &mut live);
+    }
+    for (idx, col) in scan_columns(&executors[0]).iter().enumerate() {
+        if col.get_pk_handle() {
+            live.insert(idx);
+        }
+    }
+
+    live.into_iter().collect()
+}
+
+/// Like [`prune_unused_columns`], but seeded from the request's own
+/// `output_offsets` for a tighter live set than re-deriving it from the
+/// executor tree alone. When no executor reshapes the row (a
+/// `Selection`/`TopN`/`Limit` chain, so the last executor's output slot
+/// `i` is still scan column `i`), `output_offsets` shares the scan's
+/// column-offset space, so it is remapped through the exact same table as
+/// every `ColumnRef`, in place, or it would still point at pre-compaction
+/// offsets once the scan shrinks. When an aggregation or projection is
+/// present, only it and the executors below it are analysed and
+/// rewritten; the executors above it and `output_offsets` index its output
+/// and are left as they are.
+pub fn prune_unused_columns_for(executors: &mut [Executor], output_offsets: &mut [u32]) {
+    if executors.is_empty() {
+        return;
+    }
+
+    let reshape_at = first_reshaping_executor(executors);
+    let scan_space_len = reshape_at.map_or(executors.len(), |idx| idx + 1);
+    let mut live: BTreeSet<usize> = if reshape_at.is_none() {
+        output_offsets.iter().map(|&o| o as usize).collect()
+    } else {
+        BTreeSet::new()
+    };
+    for exec in executors[..scan_space_len].iter().rev() {
+        collect_referenced_columns(exec, &mut live);
+    }
+    for (idx, col) in scan_columns(&executors[0]).iter().enumerate() {
+        if col.get_pk_handle() {
+            live.insert(idx);
+        }
+    }
+    let live: Vec<usize> = live.into_iter().collect();
+
+    let scan_columns_len = scan_columns(&executors[0]).len();
+    if live.len() == scan_columns_len {
+        return;
+    }
+
+    let remap: std::collections::HashMap<usize, usize> = live
+        .iter()
+        .enumerate()
+        .map(|(new_offset, &old_offset)| (old_offset, new_offset))
+        .collect();
+
+    restrict_scan_columns(&mut executors[0], &live);
+    for exec in executors[1..scan_space_len].iter_mut() {
+        rewrite_executor_offsets(exec, &remap);
+    }
+    if reshape_at.is_none() {
+        for offset in output_offsets.iter_mut() {
+            if let Some(&new_offset) = remap.get(&(*offset as usize)) {
+                *offset = new_offset as u32;
+            }
+        }
+    }
+}
+
+fn collect_referenced_columns(exec: &Executor, live: &mut BTreeSet<usize>) {
+    match exec.get_tp() {
+        ExecType::TypeSelection => {
+            for e in exec.get_selection().get_conditions() {
+                collect_expr_columns(e, live);
+            }
+        }
+        ExecType::TypeAggregation | ExecType::TypeStreamAgg => {
+            for e in exec.get_aggregation().get_group_by() {
+                collect_expr_columns(e, live);
+            }
+            for e in exec.get_aggregation().get_agg_func() {
+                collect_expr_columns(e, live);
+            }
+        }
+        ExecType::TypeTopN => {
+            for by in exec.get_topN().get_order_by() {
+                collect_expr_columns(by.get_expr(), live);
+            }
+        }
+        ExecType::TypeProjection => {
+            for e in exec.get_projection().get_exprs() {
+                collect_expr_columns(e, live);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn collect_expr_columns(expr: &Expr, live: &mut BTreeSet<usize>) {
+    if expr.get_tp() == ExprType::ColumnRef {
+        if let Some(offset) = column_ref_offset(expr) {
+            live.insert(offset);
+        }
+    }
+    for child in expr.get_children() {
+        collect_expr_columns(child, live);
+    }
+}
+
+fn scan_columns(exec: &Executor) -> &[ColumnInfo] {
+    match exec.get_tp() {
+        ExecType::TypeTableScan => exec.get_tbl_scan().get_columns(),
+        ExecType::TypeIndexScan => exec.get_idx_scan().get_columns(),
+        _ => &[],
+    }
+}
+
+fn restrict_scan_columns(exec: &mut Executor, live: &[usize]) {
+    // The handle/PK column (`pk_handle`) identifies the row and must
+    // survive pruning even if no expression reads it directly, since
+    // downstream executors may still need row identity (e.g. a later
+    // `Limit`/streaming chunk boundary).
+    let columns: Vec<ColumnInfo> = match exec.get_tp() {
+        ExecType::TypeTableScan => exec.take_tbl_scan().take_columns().into(),
+        ExecType::TypeIndexScan => exec.take_idx_scan().take_columns().into(),
+        _ => return,
+    };
+
+    let kept: Vec<ColumnInfo> = columns
+        .into_iter()
+        .enumerate()
+        .filter(|(idx, col)| live.contains(idx) || col.get_pk_handle())
+        .map(|(_, col)| col)
+        .collect();
+
+    match exec.get_tp() {
+        ExecType::TypeTableScan => exec.mut_tbl_scan().set_columns(kept.into()),
+        ExecType::TypeIndexScan => exec.mut_idx_scan().set_columns(kept.into()),
+        _ => {}
+    }
+}
+
+fn rewrite_executor_offsets(exec: &mut Executor, remap: &std::collections::HashMap<usize, usize>) {
+    match exec.get_tp() {
+        ExecType::TypeSelection => {
+            for e in exec.mut_selection().mut_conditions().iter_mut() {
+                rewrite_expr_offsets(e, remap);
+            }
+        }
+        ExecType::TypeAggregation | ExecType::TypeStreamAgg => {
+            for e in exec.mut_aggregation().mut_group_by().iter_mut() {
+                rewrite_expr_offsets(e, remap);
+            }
+            for e in exec.mut_aggregation().mut_agg_func().iter_mut() {
+                rewrite_expr_offsets(e, remap);
+            }
+        }
+        ExecType::TypeTopN => {
+            for by in exec.mut_topN().mut_order_by().iter_mut() {
+                rewrite_expr_offsets(by.mut_expr(), remap);
+            }
+        }
+        ExecType::TypeProjection => {
+            for e in exec.mut_projection().mut_exprs().iter_mut() {
+                rewrite_expr_offsets(e, remap);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn rewrite_expr_offsets(expr: &mut Expr, remap: &std::collections::HashMap<usize, usize>) {
+    if expr.get_tp() == ExprType::ColumnRef {
+        if let Some(offset) = column_ref_offset(expr) {
+            if let Some(&new_offset) = remap.get(&offset) {
+                set_column_ref_offset(expr, new_offset);
+            }
+        }
+    }
+    for child in expr.mut_children().iter_mut() {
+        rewrite_expr_offsets(child, remap);
+    }
+}
@@ -1,27 +1,60 @@
 // Copyright 2019 TiKV Project Authors. Licensed under Apache-2.0.
 
+mod column_pruning;
 mod storage_impl;
 
 pub use self::storage_impl::TiKVStorage;
 
 use kvproto::coprocessor::{KeyRange, Response};
+use lazy_static::lazy_static;
 use protobuf::Message;
+use tidb_query::executor::{CachedPlan, PlanCache};
 use tidb_query::storage::IntervalRange;
 use tipb::{DAGRequest, SelectResponse, StreamResponse};
 
+use self::column_pruning::prune_unused_columns_for;
 use crate::coprocessor::metrics::*;
 use crate::coprocessor::{Deadline, RequestHandler, Result};
 use crate::storage::{Statistics, Store};
 
+/// Number of distinct DAG shapes cached process-wide. TiDB resends the
+/// same query shape far more often than it introduces new ones, so a
+/// modest capacity covers the working set of a node's hot queries without
+/// holding onto long-abandoned ones.
+const PLAN_CACHE_CAPACITY: usize = 1000;
+
+lazy_static! {
+    static ref PLAN_CACHE: PlanCache<CachedPlan> = PlanCache::new(PLAN_CACHE_CAPACITY);
+}
+
 pub fn build_handler<S: Store + 'static>(
-    req: DAGRequest,
+    mut req: DAGRequest,
     ranges: Vec<KeyRange>,
     store: S,
     deadline: Deadline,
     batch_row_limit: usize,
     is_streaming: bool,
     enable_batch_if_possible: bool,
+    _region_id: u64,
 ) -> Result<Box<dyn RequestHandler>> {
+    // Drop columns the query never consumes before the executor chain is
+    // built, so both the normal and batch paths decode less per row. This
+    // is a no-op when every scanned column is already live.
+    prune_unused_columns_for(
+        req.mut_executors().as_mut_slice(),
+        req.mut_output_offsets().as_mut_slice(),
+    );
+
+    // `region_zone_map` is only refreshed by `TableCheckObserver` at the end
+    // of a split-check scan; nothing invalidates it on writes, splits, or
+    // merges in between, so a region's recorded min/max can be stale by the
+    // time a request arrives. A row written after the last split-check
+    // could fall outside the stored bounds and be pruned here without ever
+    // being scanned, turning a real row into a silently incomplete
+    // response instead of an error. Until invalidation is wired into the
+    // write/split/merge path, the zone map must not be used to skip a scan
+    // outright — only, once it is safe to do so, to order or skip within a
+    // scan that is already happening.
     let mut is_batch = false;
     if enable_batch_if_possible && !is_streaming {
         let is_supported =
@@ -47,7 +80,10 @@ pub fn build_handler<S: Store + 'static>(
     }
 }
 
-pub struct DAGHandler(tidb_query::executor::ExecutorsRunner<Statistics>);
+pub struct DAGHandler {
+    runner: tidb_query::executor::ExecutorsRunner<Statistics>,
+    is_explain: bool,
+}
 
 impl DAGHandler {
     pub fn new<S: Store + 'static>(
@@ -58,28 +94,65 @@ impl DAGHandler {
         batch_row_limit: usize,
         is_streaming: bool,
     ) -> Result<Self> {
-        Ok(Self(tidb_query::executor::ExecutorsRunner::from_request(
-            req,
-            ranges,
-            TiKVStorage::from(store),
-            deadline,
-            batch_row_limit,
-            is_streaming,
-        )?))
+        let is_explain = req.get_is_explain();
+        // Explain requests need the runner to hold on to their own
+        // `ExplainPlan`, which `from_cached_request` never builds (a
+        // cached plan is shared read-only state, and explain's annotated
+        // DOT graph is request-specific), so they always go through the
+        // uncached path.
+        let runner = if is_explain {
+            tidb_query::executor::ExecutorsRunner::from_request(
+                req,
+                ranges,
+                TiKVStorage::from(store),
+                deadline,
+                batch_row_limit,
+                is_streaming,
+            )?
+        } else {
+            tidb_query::executor::ExecutorsRunner::from_cached_request(
+                req,
+                ranges,
+                TiKVStorage::from(store),
+                deadline,
+                batch_row_limit,
+                is_streaming,
+                &PLAN_CACHE,
+            )?
+        };
+        Ok(Self { runner, is_explain })
+    }
+
+    /// Logs the executor pipeline's annotated DOT graph once the request
+    /// has run, for requests that asked for `EXPLAIN`. There's no tipb
+    /// wire field to carry a debugging graph back to TiDB, so this is a
+    /// log-only affordance operators can pull from the TiKV log rather
+    /// than a response payload.
+    fn log_explain_dot(&mut self) {
+        if !self.is_explain {
+            return;
+        }
+        if let Some(dot) = self.runner.explain_dot() {
+            info!("coprocessor DAG explain graph"; "dot" => %dot);
+        }
     }
 }
 
 impl RequestHandler for DAGHandler {
     fn handle_request(&mut self) -> Result<Response> {
-        handle_qe_response(self.0.handle_request())
+        let resp = self.runner.handle_request();
+        self.log_explain_dot();
+        handle_qe_response(resp)
     }
 
     fn handle_streaming_request(&mut self) -> Result<(Option<Response>, bool)> {
-        handle_qe_stream_response(self.0.handle_streaming_request())
+        let resp = self.runner.handle_streaming_request();
+        self.log_explain_dot();
+        handle_qe_stream_response(resp)
     }
 
     fn collect_scan_statistics(&mut self, dest: &mut Statistics) {
-        self.0.collect_storage_stats(dest);
+        self.runner.collect_storage_stats(dest);
     }
 }
 
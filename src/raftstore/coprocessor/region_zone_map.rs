@@ -0,0 +1,83 @@
+// Copyright 2019 TiKV Project Authors. Licensed under Apache-2.0.
+
+//! Region-level min/max "zone maps", the same pruning idea used by
+//! columnar block statistics (skip data whose min/max cannot satisfy the
+//! predicate) applied at TiKV's region granularity. `TableCheckObserver`
+//! already performs a single forward/reverse scan over `CF_WRITE` to find
+//! table-boundary split keys; this module turns that scan into a reusable
+//! statistics collector instead of throwing the scanned keys away.
+//!
+//! The recorded min/max is only refreshed at the end of a split-check
+//! scan, so it is not kept current with writes, splits, or merges that
+//! happen in between. That staleness window makes it unsafe to use this
+//! to prove a request's key range cannot overlap a region and skip
+//! scanning it outright: a row written after the region's last
+//! split-check could fall outside the stored bounds and silently
+//! disappear from the response. Until invalidation is wired into the
+//! write/split/merge path, treat `get_zone_map` as advisory only — safe
+//! to use for ordering or skipping within a scan that is happening
+//! regardless, not for deciding whether to scan at all.
+
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+use lazy_static::lazy_static;
+
+/// Summary statistics gathered for one region while `TableCheckObserver`
+/// scans it for split keys.
+#[derive(Clone, Debug, Default)]
+pub struct RegionZoneMap {
+    pub min_key: Vec<u8>,
+    pub max_key: Vec<u8>,
+    pub approx_row_count: u64,
+}
+
+impl RegionZoneMap {
+    /// Folds one observed key into the running min/max/count. `min_key`
+    /// and `max_key` are encoded table/row-handle keys, so a plain byte
+    /// comparison is enough to order them.
+    pub fn observe(&mut self, key: &[u8]) {
+        if self.approx_row_count == 0 || key < self.min_key.as_slice() {
+            self.min_key = key.to_vec();
+        }
+        if self.approx_row_count == 0 || key > self.max_key.as_slice() {
+            self.max_key = key.to_vec();
+        }
+        self.approx_row_count += 1;
+    }
+
+    /// Whether a scan over `[start, end)` could possibly find any row in
+    /// this region, given the region's recorded min/max. An empty
+    /// zone map (no rows ever observed) never rules a range out, since an
+    /// outdated or not-yet-populated entry must not cause false negatives.
+    pub fn can_overlap(&self, start: &[u8], end: &[u8]) -> bool {
+        if self.approx_row_count == 0 {
+            return true;
+        }
+        let start_ok = end.is_empty() || self.min_key.as_slice() < end;
+        let end_ok = start.is_empty() || self.max_key.as_slice() >= start;
+        start_ok && end_ok
+    }
+}
+
+lazy_static! {
+    static ref ZONE_MAPS: RwLock<HashMap<u64, RegionZoneMap>> = RwLock::new(HashMap::default());
+}
+
+/// Replaces the recorded zone map for `region_id` with a freshly scanned
+/// one. Called once per split-check scan, when the `Checker` that
+/// `TableCheckObserver` added for the region is dropped.
+pub fn record_zone_map(region_id: u64, zone_map: RegionZoneMap) {
+    ZONE_MAPS.write().unwrap().insert(region_id, zone_map);
+}
+
+/// Returns a copy of the recorded zone map for `region_id`, if any has
+/// been recorded yet (split-check runs periodically, so a freshly split
+/// or never-checked region simply has none).
+pub fn get_zone_map(region_id: u64) -> Option<RegionZoneMap> {
+    ZONE_MAPS.read().unwrap().get(&region_id).cloned()
+}
+
+pub fn remove_zone_map(region_id: u64) {
+    ZONE_MAPS.write().unwrap().remove(&region_id);
+}
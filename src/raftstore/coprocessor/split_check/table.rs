@@ -1,6 +1,7 @@
 // Copyright 2017 TiKV Project Authors. Licensed under Apache-2.0.
 
 use std::cmp::Ordering;
+use std::sync::Arc;
 
 use engine::rocks::{SeekKey, DB};
 use engine::CF_WRITE;
@@ -10,6 +11,7 @@ use kvproto::pdpb::CheckPolicy;
 use tidb_query::codec::table as table_codec;
 use tikv_util::keybuilder::KeyBuilder;
 
+use crate::raftstore::coprocessor::region_zone_map::{self, RegionZoneMap};
 use crate::raftstore::store::keys;
 use crate::storage::types::Key;
 
@@ -18,49 +20,152 @@ use super::super::{
 };
 use super::Host;
 
-#[derive(Default)]
+/// Where `Checker::on_kv` looks to decide that a key starts a new logical
+/// partition worth splitting on. `TableKeyspacePolicy` is TiDB's
+/// `t{table_id}` convention; a raw-KV/TxnKV deployment that doesn't lay its
+/// keyspace out that way can supply its own, e.g. [`FixedPrefixSplitPolicy`].
+pub trait KeyspaceSplitPolicy: Send + Sync {
+    /// Whether `current` belongs to a different partition than the one
+    /// `previous` (the last partition prefix seen so far, if any) names.
+    fn starts_new_partition(&self, previous: Option<&[u8]>, current: &[u8]) -> bool;
+
+    /// Extracts the partition prefix `key` should be split on, if any.
+    fn partition_prefix(&self, key: &[u8]) -> Option<Vec<u8>>;
+
+    /// Whether this policy understands TiDB's `t{table_id}` encoding well
+    /// enough to use `add_checker`'s upfront "which side of the range
+    /// already contains table data" shortcut. A keyspace that isn't laid
+    /// out that way has no such shortcut and must always fall back to a
+    /// full scan, so it should leave this `false`.
+    fn uses_table_prefix_fast_path(&self) -> bool {
+        false
+    }
+}
+
+/// TiDB's own `t{table_id}` keyspace: the existing split behavior of this
+/// checker, now behind the [`KeyspaceSplitPolicy`] trait instead of being
+/// hardwired into `Checker::on_kv`.
+pub struct TableKeyspacePolicy;
+
+impl KeyspaceSplitPolicy for TableKeyspacePolicy {
+    fn starts_new_partition(&self, previous: Option<&[u8]>, current: &[u8]) -> bool {
+        match previous {
+            Some(prefix) => !is_same_table(prefix, current),
+            // Now we meet the very first table key of this region.
+            None => is_table_key(current),
+        }
+    }
+
+    fn partition_prefix(&self, key: &[u8]) -> Option<Vec<u8>> {
+        to_encoded_table_prefix(key)
+    }
+
+    fn uses_table_prefix_fast_path(&self) -> bool {
+        true
+    }
+}
+
+/// Splits a keyspace that doesn't follow TiDB's table encoding: a
+/// partition is either everything sharing a fixed-length byte prefix, or
+/// everything up to and including the first occurrence of a delimiter
+/// byte. Lets raw-KV deployments keep their own hot prefixes in their own
+/// regions without pretending to be TiDB tables.
+pub enum FixedPrefixSplitPolicy {
+    Length(usize),
+    Delimiter(u8),
+}
+
+impl KeyspaceSplitPolicy for FixedPrefixSplitPolicy {
+    fn starts_new_partition(&self, previous: Option<&[u8]>, current: &[u8]) -> bool {
+        match previous {
+            Some(prefix) => self.partition_prefix(current).as_deref() != Some(prefix),
+            // Mirror `TableKeyspacePolicy`'s first-key handling: the very
+            // first key of a region only starts a partition if it actually
+            // has one (e.g. long enough for `Length`, contains the
+            // delimiter for `Delimiter`), rather than unconditionally
+            // emitting a split key that `max_splits` then has to pay for.
+            None => self.partition_prefix(current).is_some(),
+        }
+    }
+
+    fn partition_prefix(&self, key: &[u8]) -> Option<Vec<u8>> {
+        match *self {
+            FixedPrefixSplitPolicy::Length(len) => {
+                if key.len() < len {
+                    None
+                } else {
+                    Some(key[..len].to_vec())
+                }
+            }
+            FixedPrefixSplitPolicy::Delimiter(delim) => key
+                .iter()
+                .position(|&b| b == delim)
+                .map(|pos| key[..=pos].to_vec()),
+        }
+    }
+}
+
 pub struct Checker {
     first_encoded_table_prefix: Option<Vec<u8>>,
-    split_key: Option<Vec<u8>>,
+    split_keys: Vec<Vec<u8>>,
+    max_splits: usize,
     policy: CheckPolicy,
+    region_id: u64,
+    zone_map: RegionZoneMap,
+    keyspace_policy: Arc<dyn KeyspaceSplitPolicy>,
+}
+
+impl Default for Checker {
+    fn default() -> Self {
+        Checker {
+            first_encoded_table_prefix: None,
+            split_keys: Vec::new(),
+            max_splits: 0,
+            policy: CheckPolicy::default(),
+            region_id: 0,
+            zone_map: RegionZoneMap::default(),
+            keyspace_policy: Arc::new(TableKeyspacePolicy),
+        }
+    }
 }
 
 impl SplitChecker for Checker {
-    /// Feed keys in order to find the split key.
-    /// If `current_data_key` does not belong to `status.first_encoded_table_prefix`.
-    /// it returns the encoded table prefix of `current_data_key`.
+    /// Feed keys in order and collect a split key each time `current_data_key`
+    /// crosses into a new partition of `keyspace_policy`, so a region
+    /// spanning many small partitions can be broken up in one pass instead
+    /// of one split per check round.
     fn on_kv(&mut self, _: &mut ObserverContext<'_>, entry: &KeyEntry) -> bool {
-        if self.split_key.is_some() {
+        let current_encoded_key = keys::origin_key(entry.key());
+        // The split-key search already performs a single scan over every
+        // key in the region; piggyback the region's min/max/approx-count
+        // zone map on that same pass instead of throwing the scanned keys
+        // away once a split key is found.
+        self.zone_map.observe(current_encoded_key);
+
+        if self.split_keys.len() >= self.max_splits {
             return true;
         }
 
-        let current_encoded_key = keys::origin_key(entry.key());
+        let last_partition_prefix = self
+            .split_keys
+            .last()
+            .map(Vec::as_slice)
+            .or_else(|| self.first_encoded_table_prefix.as_deref());
 
-        let split_key = if self.first_encoded_table_prefix.is_some() {
-            if !is_same_table(
-                self.first_encoded_table_prefix.as_ref().unwrap(),
-                current_encoded_key,
-            ) {
-                // Different tables.
-                Some(current_encoded_key)
-            } else {
-                None
+        if self
+            .keyspace_policy
+            .starts_new_partition(last_partition_prefix, current_encoded_key)
+        {
+            if let Some(prefix) = self.keyspace_policy.partition_prefix(current_encoded_key) {
+                self.split_keys.push(prefix);
             }
-        } else if is_table_key(current_encoded_key) {
-            // Now we meet the very first table key of this region.
-            Some(current_encoded_key)
-        } else {
-            None
-        };
-        self.split_key = split_key.and_then(to_encoded_table_prefix);
-        self.split_key.is_some()
+        }
+
+        self.split_keys.len() >= self.max_splits
     }
 
     fn split_keys(&mut self) -> Vec<Vec<u8>> {
-        match self.split_key.take() {
-            None => vec![],
-            Some(key) => vec![key],
-        }
+        std::mem::take(&mut self.split_keys)
     }
 
     fn policy(&self) -> CheckPolicy {
@@ -68,8 +173,37 @@ impl SplitChecker for Checker {
     }
 }
 
-#[derive(Default)]
-pub struct TableCheckObserver;
+/// Upper bound on how many partition split keys a single scan emits.
+/// Without a cap, a region left unsplit for a long time after a bulk import
+/// of many small partitions could force one split-check round to buffer an
+/// unbounded number of keys before `split_keys` is ever read.
+const DEFAULT_MAX_TABLE_SPLITS_PER_CHECK: usize = 1024;
+
+pub struct TableCheckObserver {
+    max_table_splits_per_check: usize,
+    keyspace_policy: Arc<dyn KeyspaceSplitPolicy>,
+}
+
+impl TableCheckObserver {
+    pub fn new(
+        max_table_splits_per_check: usize,
+        keyspace_policy: Arc<dyn KeyspaceSplitPolicy>,
+    ) -> Self {
+        TableCheckObserver {
+            max_table_splits_per_check,
+            keyspace_policy,
+        }
+    }
+}
+
+impl Default for TableCheckObserver {
+    fn default() -> Self {
+        TableCheckObserver {
+            max_table_splits_per_check: DEFAULT_MAX_TABLE_SPLITS_PER_CHECK,
+            keyspace_policy: Arc::new(TableKeyspacePolicy),
+        }
+    }
+}
 
 impl Coprocessor for TableCheckObserver {}
 
@@ -82,6 +216,22 @@ impl SplitCheckObserver for TableCheckObserver {
         policy: CheckPolicy,
     ) {
         let region = ctx.region();
+
+        if !self.keyspace_policy.uses_table_prefix_fast_path() {
+            // Non-table keyspaces have no cheap "does this range already
+            // contain table data" shortcut to compare against
+            // `table_codec::TABLE_PREFIX`; always run a full scan and let
+            // the policy discover partitions as it goes.
+            host.add_checker(Box::new(Checker {
+                policy,
+                region_id: region.get_id(),
+                max_splits: self.max_table_splits_per_check,
+                keyspace_policy: Arc::clone(&self.keyspace_policy),
+                ..Default::default()
+            }));
+            return;
+        }
+
         if is_same_table(region.get_start_key(), region.get_end_key()) {
             // Region is inside a table, skip for saving IO.
             return;
@@ -110,6 +260,9 @@ impl SplitCheckObserver for TableCheckObserver {
             // is less than TABLE_PREFIX_KEY_LEN.
             host.add_checker(Box::new(Checker {
                 policy,
+                region_id: region.get_id(),
+                max_splits: self.max_table_splits_per_check,
+                keyspace_policy: Arc::clone(&self.keyspace_policy),
                 ..Default::default()
             }));
             return;
@@ -161,12 +314,24 @@ impl SplitCheckObserver for TableCheckObserver {
         }
         host.add_checker(Box::new(Checker {
             first_encoded_table_prefix,
-            split_key,
+            split_keys: split_key.into_iter().collect(),
+            max_splits: self.max_table_splits_per_check,
             policy,
+            region_id: region.get_id(),
+            zone_map: RegionZoneMap::default(),
+            keyspace_policy: Arc::clone(&self.keyspace_policy),
         }));
     }
 }
 
+impl Drop for Checker {
+    fn drop(&mut self) {
+        if self.region_id != 0 {
+            region_zone_map::record_zone_map(self.region_id, std::mem::take(&mut self.zone_map));
+        }
+    }
+}
+
 fn last_key_of_region(db: &DB, region: &Region) -> Result<Option<Vec<u8>>> {
     let start_key = keys::enc_start_key(region);
     let end_key = keys::enc_end_key(region);